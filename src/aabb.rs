@@ -43,6 +43,15 @@ impl AABB {
         self.maximum
     }
 
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> Float {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn hit(&self, ray: &Ray, t_min: Float, t_max: Float) -> bool {
         let dir: [Float; 3] = ray.direction().into();
         let ori: [Float; 3] = ray.origin().into();
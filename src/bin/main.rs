@@ -1,35 +1,75 @@
 use std::{
     fs::File,
-    intrinsics::transmute,
-    io::{BufWriter, Read, Write},
+    io::{self, BufWriter, Write},
     mem::size_of,
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
-    thread::spawn,
 };
 
-use rand::random;
 use ray_tracing_weekend::*;
 
-fn write_image_png(data: &[u8], width: u32, height: u32, w: impl Write) {
+fn write_image_png(data: &[u8], width: u32, height: u32, w: impl Write) -> io::Result<()> {
     let mut encoder = png::Encoder::new(w, width, height);
     encoder.set_color(png::ColorType::Rgb);
     encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
-    writer.write_image_data(data).unwrap();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    Ok(())
 }
 
-fn read_png(file: impl Read) -> (Vec<u8>, u32, u32) {
-    let mut decoder = png::Decoder::new(file);
-    decoder.set_transformations(png::Transformations::EXPAND);
-    let mut reader = decoder.read_info().unwrap();
-    let mut buf = vec![0; reader.output_buffer_size()];
-    let info = reader.next_frame(&mut buf).unwrap();
-    buf.truncate(info.buffer_size());
-    (buf, info.width, info.height)
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    Png,
+    Hdr,
+    Exr(ExrPixelType),
 }
 
-fn random_scene() -> HittableList {
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(Self::Png),
+            "hdr" => Some(Self::Hdr),
+            "exr" => Some(Self::Exr(ExrPixelType::Half)),
+            "exr32" => Some(Self::Exr(ExrPixelType::Float)),
+            _ => None,
+        }
+    }
+
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Hdr => "hdr",
+            Self::Exr(_) => "exr",
+        }
+    }
+
+    fn write(
+        self,
+        image: Vec<Color>,
+        width: u32,
+        height: u32,
+        display: &DisplayTransform,
+        w: impl Write,
+    ) -> io::Result<()> {
+        match self {
+            Self::Png => {
+                let data: Vec<[u8; 3]> = image
+                    .into_iter()
+                    .map(|color| display.apply(color))
+                    .collect();
+                let data = unsafe {
+                    let ptr = data.as_ptr();
+                    std::slice::from_raw_parts(ptr as *const u8, data.len() * size_of::<[u8; 3]>())
+                };
+                write_image_png(data, width, height, w)
+            }
+            Self::Hdr => write_hdr(&image, width, height, w),
+            Self::Exr(pixel_type) => write_exr(&image, width, height, pixel_type, w),
+        }
+    }
+}
+
+fn random_scene(rng: &mut Rng) -> HittableList {
     let mut world = HittableList::new();
 
     let checker =
@@ -44,14 +84,14 @@ fn random_scene() -> HittableList {
     for a in -11..11 {
         for b in -11..11 {
             let center = Point3::new(
-                a as Float + 0.9 * random::<Float>(),
+                a as Float + 0.9 * rng.float(),
                 0.2,
-                b as Float + 0.9 * random::<Float>(),
+                b as Float + 0.9 * rng.float(),
             );
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let choose = random::<Float>();
+                let choose = rng.float();
                 if choose < 0.8 {
-                    let center1 = center + Vec3::new(0.0, random::<Float>() * 0.5, 0.0);
+                    let center1 = center + Vec3::new(0.0, rng.float() * 0.5, 0.0);
                     world.add(MovingSphere::new(
                         center,
                         center1,
@@ -59,7 +99,7 @@ fn random_scene() -> HittableList {
                         1.0,
                         0.2,
                         Arc::new(Lambertian::new_with_color(
-                            Color::random() * Color::random(),
+                            Color::random(rng) * Color::random(rng),
                         )),
                     ));
                 } else if choose < 0.95 {
@@ -67,8 +107,8 @@ fn random_scene() -> HittableList {
                         center,
                         0.2,
                         Arc::new(Metal::new(
-                            Color::random_range(0.5..1.0),
-                            random::<Float>() * 0.5,
+                            Color::random_range(rng, 0.5..1.0),
+                            rng.float() * 0.5,
                         )),
                     ));
                 } else {
@@ -134,8 +174,7 @@ fn two_perlin_spheres() -> HittableList {
 }
 
 fn earth() -> HittableList {
-    let (data, width, height) = read_png(File::open(Path::new(r"./earthmap.png")).unwrap());
-    let earth_texture = ImageTexture::new(data, width, height);
+    let earth_texture = ImageTexture::open("./earthmap.png").unwrap();
     let earth_surface = Lambertian::new_with_texture(earth_texture);
     let globe = Sphere::new(Point3::new(0.0, 0.0, 0.0), 2.0, Arc::new(earth_surface));
     HittableList::new_with(globe)
@@ -241,7 +280,7 @@ fn cornell_smoke() -> HittableList {
     objects
 }
 
-fn final_scene() -> HittableList {
+fn final_scene(rng: &mut Rng) -> HittableList {
     let mut boxes = HittableList::new();
     let ground = Arc::new(Lambertian::new_with_color(Color::new(0.48, 0.83, 0.53)));
 
@@ -253,7 +292,7 @@ fn final_scene() -> HittableList {
             let z0 = -1000.0 + (j as Float) * w;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = random::<Float>() * 100.0 + 1.0;
+            let y1 = rng.float() * 100.0 + 1.0;
             let z1 = z0 + w;
             boxes.add(BlockBox::new(
                 Point3::new(x0, y0, z0),
@@ -310,8 +349,7 @@ fn final_scene() -> HittableList {
         Arc::new(SolidColor::from(Color::new(1.0, 1.0, 1.0))),
     ));
 
-    let (data, width, height) = read_png(File::open(Path::new(r"./earthmap.png")).unwrap());
-    let earth_texture = ImageTexture::new(data, width, height);
+    let earth_texture = ImageTexture::open("./earthmap.png").unwrap();
     let earth_surface = Lambertian::new_with_texture(earth_texture);
     objects.add(Sphere::new(
         Point3::new(400.0, 200.0, 400.0),
@@ -330,7 +368,7 @@ fn final_scene() -> HittableList {
     let ns = 1000;
     for _ in 0..ns {
         boxes.add(Sphere::new(
-            Point3::random_range(0.0..165.0),
+            Point3::random_range(rng, 0.0..165.0),
             10.0,
             white.clone(),
         ));
@@ -344,150 +382,465 @@ fn final_scene() -> HittableList {
     objects
 }
 
-fn main() {
-    let mut aspect_ratio = 16.0 / 9.0;
-    let mut image_width = 400;
-    let mut samples_per_pixel = 10;
-    let max_depth = 50;
-
-    let mut lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let mut lookat = Point3::new(0.0, 0.0, 0.0);
-    let mut vfov = 20.0;
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let mut aperture = 0.1;
-    let mut focus_dist = 10.0;
-    let mut background = Color::new(0.7, 0.8, 1.0);
-
-    let mut lights = HittableList::new();
-    lights.add(XzRect::new(
-        213.0,
-        343.0,
-        227.0,
-        332.0,
-        554.0,
-        Arc::new(EmptyMaterial),
-    ));
-    lights.add(Sphere::new(
-        Point3::new(190.0, 90.0, 190.0),
-        90.0,
-        Arc::new(EmptyMaterial),
-    ));
-    let lights: Arc<dyn Hittable> = Arc::new(lights);
-
-    let world = match 6 {
-        1 => random_scene(),
+/// Builds a built-in scene; random placements are drawn from `seed`.
+fn builtin_scene(index: u32, seed: u64) -> Scene {
+    let mut rng = Rng::new(seed, 0);
+    match index {
+        1 => Scene::new(random_scene(&mut rng)),
         2 => {
-            aperture = 0.0;
-            two_spheres()
+            let mut scene = Scene::new(two_spheres());
+            scene.camera.aperture = 0.0;
+            scene
         }
-        3 => two_perlin_spheres(),
-        4 => earth(),
+        3 => Scene::new(two_perlin_spheres()),
+        4 => Scene::new(earth()),
         5 => {
-            background = Color::default();
-            samples_per_pixel = 40;
-            focus_dist = 20.0;
-            lookfrom = Point3::new(26.0, 3.0, 6.0);
-            lookat = Point3::new(0.0, 2.0, 0.0);
-            simple_light()
+            let mut scene = Scene::new(simple_light());
+            scene.environment = Arc::new(ConstantEnvironment::new(Color::default()));
+            scene.samples_per_pixel = 40;
+            scene.camera.focus_dist = 20.0;
+            scene.camera.lookfrom = Point3::new(26.0, 3.0, 6.0);
+            scene.camera.lookat = Point3::new(0.0, 2.0, 0.0);
+            scene
         }
         6 => {
-            aspect_ratio = 1.0;
-            image_width = 400;
-            samples_per_pixel = 100;
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            background = Color::default();
-            vfov = 40.0;
-            aperture = 0.0;
-            cornell_box()
+            let mut scene = Scene::new(cornell_box());
+            scene.lights.add(Sphere::new(
+                Point3::new(190.0, 90.0, 190.0),
+                90.0,
+                Arc::new(EmptyMaterial),
+            ));
+            scene.aspect_ratio = 1.0;
+            scene.image_width = 400;
+            scene.samples_per_pixel = 100;
+            scene.camera.lookfrom = Point3::new(278.0, 278.0, -800.0);
+            scene.camera.lookat = Point3::new(278.0, 278.0, 0.0);
+            scene.environment = Arc::new(ConstantEnvironment::new(Color::default()));
+            scene.camera.vfov = 40.0;
+            scene.camera.aperture = 0.0;
+            scene
         }
         7 => {
-            aspect_ratio = 1.0;
-            image_width = 600;
-            samples_per_pixel = 20;
-            lookfrom = Point3::new(278.0, 278.0, -800.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            background = Color::default();
-            vfov = 40.0;
-            aperture = 0.0;
-            cornell_smoke()
+            let mut scene = Scene::new(cornell_smoke());
+            scene.aspect_ratio = 1.0;
+            scene.image_width = 600;
+            scene.samples_per_pixel = 20;
+            scene.camera.lookfrom = Point3::new(278.0, 278.0, -800.0);
+            scene.camera.lookat = Point3::new(278.0, 278.0, 0.0);
+            scene.environment = Arc::new(ConstantEnvironment::new(Color::default()));
+            scene.camera.vfov = 40.0;
+            scene.camera.aperture = 0.0;
+            scene
         }
         _ => {
-            aspect_ratio = 1.0;
-            image_width = 800;
-            samples_per_pixel = 100;
-            background = Color::default();
-            lookfrom = Point3::new(478.0, 278.0, -600.0);
-            lookat = Point3::new(278.0, 278.0, 0.0);
-            vfov = 40.0;
-            aperture = 0.0;
-            final_scene()
+            let mut scene = Scene::new(final_scene(&mut rng));
+            scene.aspect_ratio = 1.0;
+            scene.image_width = 800;
+            scene.samples_per_pixel = 100;
+            scene.environment = Arc::new(ConstantEnvironment::new(Color::default()));
+            scene.camera.lookfrom = Point3::new(478.0, 278.0, -600.0);
+            scene.camera.lookat = Point3::new(278.0, 278.0, 0.0);
+            scene.camera.vfov = 40.0;
+            scene.camera.aperture = 0.0;
+            scene
         }
-    };
+    }
+}
 
-    let image_height = (image_width as Float / aspect_ratio) as u32;
+const BUILTIN_SCENES: [&str; 8] = [
+    "random",
+    "two_spheres",
+    "two_perlin_spheres",
+    "earth",
+    "simple_light",
+    "cornell_box",
+    "cornell_smoke",
+    "final",
+];
+
+const USAGE: &str = "\
+Usage: main [OPTIONS]
+
+Options:
+  -s, --scene <NAME|FILE>  built-in scene name or scene description file
+                           [default: cornell_box]
+  -w, --width <PIXELS>     image width
+  -H, --height <PIXELS>    image height, keeps the aspect ratio if only one of
+                           width and height is given
+      --spp <N>            samples per pixel
+      --depth <N>          maximum number of bounces
+      --min-bounces <N>    bounces before Russian roulette may end a path,
+                           0 to apply it from the first [default: 5]
+      --mis <HEURISTIC>    weighting of light and material sampling
+                           [default: power] [possible values: balance, power]
+      --mode <MODE>        light transport in RGB or at sampled wavelengths,
+                           which shows dispersion [default: rgb]
+                           [possible values: rgb, spectral]
+      --adaptive <NOISE>   sample adaptively until the relative standard error
+                           of every pixel is below NOISE, e.g. 0.02, within
+                           a budget of --spp samples per pixel on average
+      --min-spp <N>        samples of every pixel in adaptive mode
+                           [default: spp/4, at least 8]
+      --max-spp <N>        most samples of a pixel in adaptive mode
+                           [default: 4*spp]
+      --sample-counts <PATH>
+                           also write the samples per pixel as an image,
+                           white for the most
+  -j, --threads <N>        number of render threads [default: number of CPUs]
+      --sampler <NAME>     sample sequence [default: sobol] [possible values:
+                           independent, stratified, halton, sobol]
+      --seed <N>           seed of the built-in scenes and of the sampling,
+                           equal seeds render identical images [default: 0]
+  -o, --output <PATH>      output image [default: ./output.png]
+      --format <FORMAT>    output format, guessed from the output path if
+                           omitted [possible values: png, hdr, exr, exr32]
+                           png is gamma corrected, hdr and exr (half float)
+                           or exr32 (float) store linear radiance; must
+                           match the extension of the output path, if any
+      --tonemap <CURVE>    tone mapping for png output [default: none]
+                           [possible values: none, reinhard, reinhard-ext,
+                           aces]
+      --white <LUMINANCE>  white point of reinhard-ext [default: 4]
+      --exposure <STOPS>   exposure adjustment for png output [default: 0]
+  -h, --help               print this help
+
+Built-in scenes: random, two_spheres, two_perlin_spheres, earth, simple_light,
+cornell_box, cornell_smoke, final";
+
+struct Options {
+    scene: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<i32>,
+    min_bounces: Option<i32>,
+    mis: MisHeuristic,
+    mode: RenderMode,
+    noise_threshold: Option<Float>,
+    min_samples: Option<u32>,
+    max_samples: Option<u32>,
+    sample_counts: Option<(PathBuf, OutputFormat)>,
+    n_threads: Option<u32>,
+    sampler: SamplerKind,
+    seed: u64,
+    output: PathBuf,
+    format: OutputFormat,
+    display: DisplayTransform,
+}
 
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        vfov,
-        aspect_ratio,
-        aperture,
-        focus_dist,
-        0.0,
-        1.0,
-    );
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Self {
+            scene: "cornell_box".to_owned(),
+            width: None,
+            height: None,
+            samples_per_pixel: None,
+            max_depth: None,
+            min_bounces: None,
+            mis: MisHeuristic::Power,
+            mode: RenderMode::Rgb,
+            noise_threshold: None,
+            min_samples: None,
+            max_samples: None,
+            sample_counts: None,
+            n_threads: None,
+            sampler: SamplerKind::Sobol,
+            seed: 0,
+            output: PathBuf::from("./output.png"),
+            format: OutputFormat::Png,
+            display: DisplayTransform::default(),
+        };
+        let mut format = None;
+        let mut tone_map = "none".to_owned();
+        let mut white = 4.0;
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg, None),
+            };
+            if flag == "-h" || flag == "--help" {
+                return Ok(None);
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("missing value for `{}`", flag)),
+            };
+            match flag.as_str() {
+                "-s" | "--scene" => options.scene = value,
+                "-w" | "--width" => options.width = Some(parse_positive(&flag, &value)?),
+                "-H" | "--height" => options.height = Some(parse_positive(&flag, &value)?),
+                "--spp" => options.samples_per_pixel = Some(parse_positive(&flag, &value)?),
+                "--depth" => options.max_depth = Some(parse_positive_i32(&flag, &value)?),
+                "--min-bounces" => {
+                    options.min_bounces = Some(parse_non_negative_i32(&flag, &value)?)
+                }
+                "--mis" => {
+                    options.mis = MisHeuristic::from_name(&value)
+                        .ok_or_else(|| format!("unknown heuristic `{}`", value))?
+                }
+                "--mode" => {
+                    options.mode = RenderMode::from_name(&value)
+                        .ok_or_else(|| format!("unknown mode `{}`", value))?
+                }
+                "--adaptive" => {
+                    let threshold = parse_float(&flag, &value)?;
+                    if threshold <= 0.0 {
+                        return Err("`--adaptive` must be positive".to_owned());
+                    }
+                    options.noise_threshold = Some(threshold);
+                }
+                "--min-spp" => options.min_samples = Some(parse_positive(&flag, &value)?),
+                "--max-spp" => options.max_samples = Some(parse_positive(&flag, &value)?),
+                "--sample-counts" => {
+                    let path = PathBuf::from(value);
+                    let format = guess_format(&path)?;
+                    options.sample_counts = Some((path, format));
+                }
+                "-j" | "--threads" => options.n_threads = Some(parse_positive(&flag, &value)?),
+                "--sampler" => {
+                    options.sampler = SamplerKind::from_name(&value)
+                        .ok_or_else(|| format!("unknown sampler `{}`", value))?
+                }
+                "--seed" => {
+                    options.seed = value.parse().map_err(|_| {
+                        format!("`{}` expects a non-negative integer, got `{}`", flag, value)
+                    })?
+                }
+                "-o" | "--output" => options.output = PathBuf::from(value),
+                "--format" => format = Some(value),
+                "--tonemap" => tone_map = value,
+                "--white" => white = parse_float(&flag, &value)?,
+                "--exposure" => options.display.exposure = parse_float(&flag, &value)?,
+                _ => return Err(format!("unknown option `{}`", flag)),
+            }
+        }
 
-    let world_ref = unsafe { transmute::<_, &'static HittableList>(&world) };
-    let lights_ref = unsafe { transmute::<_, &'static dyn Hittable>(&*lights) };
-
-    let n_threads = 10;
-
-    let threads: Vec<_> = (0..n_threads)
-        .map(|_| {
-            spawn(move || {
-                render(
-                    world_ref,
-                    lights_ref,
-                    background,
-                    cam,
-                    image_width,
-                    image_height,
-                    samples_per_pixel,
-                    max_depth,
-                )
-            })
-        })
-        .collect();
+        let extension = options
+            .output
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        options.format = match format {
+            Some(name) => {
+                let format = OutputFormat::from_name(&name)
+                    .ok_or_else(|| format!("unsupported output format `{}`", name))?;
+                if extension
+                    .as_deref()
+                    .is_some_and(|extension| extension != format.extension())
+                {
+                    return Err(format!(
+                        "output `{}` does not match the format `{}`",
+                        options.output.display(),
+                        name
+                    ));
+                }
+                format
+            }
+            None => guess_format(&options.output)?,
+        };
 
-    let images = threads.into_iter().map(|th| th.join().unwrap());
+        if options.noise_threshold.is_none()
+            && (options.min_samples.is_some() || options.max_samples.is_some())
+        {
+            return Err("`--min-spp` and `--max-spp` need `--adaptive`".to_owned());
+        }
+        if white <= 0.0 {
+            return Err("`--white` must be positive".to_owned());
+        }
+        options.display.tone_map = match tone_map.as_str() {
+            "none" => Arc::new(Linear),
+            "reinhard" => Arc::new(Reinhard),
+            "reinhard-ext" => Arc::new(ExtendedReinhard::new(white)),
+            "aces" => Arc::new(AcesFilmic),
+            _ => return Err(format!("unknown tone mapping `{}`", tone_map)),
+        };
+
+        Ok(Some(options))
+    }
 
-    let image = images
-        .reduce(|mut accum, item| {
-            for (i, color) in item.into_iter().enumerate() {
-                accum[i] += color;
+    fn load_scene(&self) -> Result<Scene, String> {
+        let mut scene = match BUILTIN_SCENES.iter().position(|name| *name == self.scene) {
+            Some(index) => builtin_scene(index as u32 + 1, self.seed),
+            None if Path::new(&self.scene).is_file() => {
+                Scene::load(&self.scene).map_err(|e| format!("{}: {}", self.scene, e))?
             }
-            accum
-        })
-        .unwrap();
-
-    let data: Vec<[u8; 3]> = image
-        .into_iter()
-        .map(|color| {
-            (color / (samples_per_pixel * n_threads) as Float)
-                .apply(Float::sqrt)
-                .into()
+            None => return Err(format!("no built-in scene or file named `{}`", self.scene)),
+        };
+
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                scene.image_width = width;
+                scene.aspect_ratio = width as Float / height as Float;
+            }
+            (Some(width), None) => scene.image_width = width,
+            (None, Some(height)) => {
+                scene.image_width = (height as Float * scene.aspect_ratio).round() as u32
+            }
+            (None, None) => {}
+        }
+        if scene.image_width < 2 || scene.image_height() < 2 {
+            return Err("the image must be at least 2x2 pixels".to_owned());
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            scene.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            scene.max_depth = max_depth;
+        }
+        Ok(scene)
+    }
+}
+
+fn guess_format(path: &Path) -> Result<OutputFormat, String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+        .and_then(OutputFormat::from_name)
+        .ok_or_else(|| {
+            format!(
+                "cannot guess the output format of `{}`, use --format",
+                path.display()
+            )
         })
-        .collect();
+}
 
-    let path = Path::new(r"./output.png");
-    let file = File::create(path).unwrap();
+fn write_output(
+    path: &Path,
+    format: OutputFormat,
+    image: Vec<Color>,
+    settings: &RenderSettings,
+    display: &DisplayTransform,
+) {
+    let file = File::create(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot create {}: {}", path.display(), e);
+        std::process::exit(1);
+    });
     let w = BufWriter::new(file);
 
-    let data = unsafe {
-        let ptr = data.as_ptr();
-        std::slice::from_raw_parts(ptr as *const u8, data.len() * size_of::<[u8; 3]>())
+    if let Err(e) = format.write(
+        image,
+        settings.image_width,
+        settings.image_height,
+        display,
+        w,
+    ) {
+        eprintln!("error: cannot write {}: {}", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_float(flag: &str, value: &str) -> Result<Float, String> {
+    match value.parse::<Float>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        _ => Err(format!("`{}` expects a number, got `{}`", flag, value)),
+    }
+}
+
+fn parse_positive(flag: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "`{}` expects a positive integer, got `{}`",
+            flag, value
+        )),
+    }
+}
+
+fn parse_positive_i32(flag: &str, value: &str) -> Result<i32, String> {
+    i32::try_from(parse_positive(flag, value)?)
+        .map_err(|_| format!("`{}` expects at most {}, got `{}`", flag, i32::MAX, value))
+}
+
+fn parse_non_negative_i32(flag: &str, value: &str) -> Result<i32, String> {
+    match value.parse() {
+        Ok(n) if n >= 0 => Ok(n),
+        _ => Err(format!(
+            "`{}` expects a non-negative integer, got `{}`",
+            flag, value
+        )),
+    }
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        }
     };
-    write_image_png(data, image_width, image_height, w);
+    let scene = options.load_scene().unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        std::process::exit(1);
+    });
+    let mut settings = RenderSettings::new(scene.image_width, scene.image_height());
+    settings.samples_per_pixel = scene.samples_per_pixel;
+    settings.max_depth = scene.max_depth;
+    if let Some(min_bounces) = options.min_bounces {
+        settings.min_bounces = min_bounces;
+    }
+    settings.mis = options.mis;
+    settings.mode = options.mode;
+    settings.sampler = options.sampler;
+    settings.seed = options.seed;
+    if let Some(noise_threshold) = options.noise_threshold {
+        let mut adaptive = AdaptiveSettings::new(noise_threshold, settings.samples_per_pixel);
+        if let Some(min_samples) = options.min_samples {
+            adaptive.min_samples = min_samples;
+        }
+        if let Some(max_samples) = options.max_samples {
+            adaptive.max_samples = max_samples;
+        }
+        adaptive.max_samples = adaptive.max_samples.max(adaptive.min_samples);
+        settings.adaptive = Some(adaptive);
+    }
+    if let Some(n_threads) = options.n_threads {
+        settings.n_threads = n_threads as usize;
+    }
+
+    let output = render(
+        &scene.world,
+        &scene.light_list(),
+        &scene.lights,
+        scene.environment.as_ref(),
+        &scene.camera(),
+        &settings,
+        &|done, total| {
+            eprint!("\rrendering {:3}%", done * 100 / total);
+            if done == total {
+                eprintln!();
+            }
+        },
+    );
+
+    write_output(
+        &options.output,
+        options.format,
+        output.image,
+        &settings,
+        &options.display,
+    );
+    if let Some((path, format)) = &options.sample_counts {
+        let max = output.sample_counts.iter().copied().max().unwrap_or(1) as Float;
+        let image = output
+            .sample_counts
+            .iter()
+            .map(|&n| Color::new(1.0, 1.0, 1.0) * (n as Float / max))
+            .collect();
+        write_output(
+            path,
+            *format,
+            image,
+            &settings,
+            &DisplayTransform::default(),
+        );
+    }
 }
@@ -1,7 +1,8 @@
 use std::sync::Arc;
 
 use crate::{
-    Float, HitRecord, Hittable, HittableList, Material, Point3, Ray, XyRect, XzRect, YzRect, AABB,
+    FlipFace, Float, HitRecord, Hittable, HittableList, Material, Point3, Ray, XyRect, XzRect,
+    YzRect, AABB,
 };
 
 pub struct BlockBox {
@@ -14,6 +15,7 @@ impl BlockBox {
     pub fn new(p0: Point3, p1: Point3, material: Arc<dyn Material>) -> Self {
         let mut sides = HittableList::new();
 
+        // the sides at the minimum of each axis are flipped to face outwards
         sides.add(XyRect::new(
             p0.x(),
             p1.x(),
@@ -22,14 +24,14 @@ impl BlockBox {
             p1.z(),
             material.clone(),
         ));
-        sides.add(XyRect::new(
+        sides.add(FlipFace::new(Arc::new(XyRect::new(
             p0.x(),
             p1.x(),
             p0.y(),
             p1.y(),
             p0.z(),
             material.clone(),
-        ));
+        ))));
 
         sides.add(XzRect::new(
             p0.x(),
@@ -39,14 +41,14 @@ impl BlockBox {
             p1.y(),
             material.clone(),
         ));
-        sides.add(XzRect::new(
+        sides.add(FlipFace::new(Arc::new(XzRect::new(
             p0.x(),
             p1.x(),
             p0.z(),
             p1.z(),
             p0.y(),
             material.clone(),
-        ));
+        ))));
 
         sides.add(YzRect::new(
             p0.y(),
@@ -56,14 +58,14 @@ impl BlockBox {
             p1.x(),
             material.clone(),
         ));
-        sides.add(YzRect::new(
+        sides.add(FlipFace::new(Arc::new(YzRect::new(
             p0.y(),
             p1.y(),
             p0.z(),
             p1.z(),
             p0.x(),
             material,
-        ));
+        ))));
 
         Self {
             box_min: p0,
@@ -85,4 +87,8 @@ impl Hittable for BlockBox {
     {
         self.sides.hit(ray, t_min, t_max, rec)
     }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.sides.collect_lights(lights);
+    }
 }
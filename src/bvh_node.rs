@@ -1,62 +1,287 @@
 use std::sync::Arc;
 
-use rand::random;
+use crate::{Color, Float, HitRecord, Hittable, HittableList, Point3, Ray, Vec3, AABB};
 
-use crate::{Float, HitRecord, Hittable, Ray, Vec3, AABB};
+const BIN_COUNT: usize = 12;
+/// Cost of visiting a node relative to intersecting one primitive.
+const TRAVERSAL_COST: Float = 0.5;
+/// Depth up to which traversals keep the nodes left to visit on the call
+/// stack rather than the heap.
+const INLINE_STACK_DEPTH: usize = 64;
 
-pub struct BvhNode {
+struct Primitive {
+    object: Arc<dyn Hittable>,
     bbox: AABB,
-    left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+    centroid: Point3,
 }
 
-impl BvhNode {
-    pub fn new(src_objects: &[Arc<dyn Hittable>], time0: Float, time1: Float) -> BvhNode {
-        let mut objects = src_objects.to_owned();
-        let axis = random::<usize>() % 3;
-        let f = match axis {
-            0 => Vec3::x,
-            1 => Vec3::y,
-            _ => Vec3::z,
-        };
-        let comparator = |a: &Arc<dyn Hittable>, b: &Arc<dyn Hittable>| {
-            let mut box_a = AABB::default();
-            let mut box_b = AABB::default();
-            if !a.bounding_box(0.0, 0.0, &mut box_a) || !b.bounding_box(0.0, 0.0, &mut box_b) {
-                eprintln!("No bouding box in bvh_node contructor.");
-            }
-            f(&box_a.min()).partial_cmp(&f(&box_b.min())).unwrap()
+fn axis_value(v: &Vec3, axis: usize) -> Float {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn enclose(primitives: &[Primitive]) -> AABB {
+    primitives
+        .iter()
+        .map(|p| p.bbox)
+        .reduce(AABB::surrounding_box)
+        .unwrap_or_default()
+}
+
+/// A SAH split: the axis, the number of primitives going left and the cost
+/// relative to one primitive intersection.
+#[derive(Clone, Copy)]
+struct Split {
+    axis: usize,
+    left_count: usize,
+    cost: Float,
+}
+
+/// Finds the best binned SAH split of `primitives` and sorts them along its
+/// axis.
+fn find_split(primitives: &mut [Primitive], bbox: &AABB) -> Option<Split> {
+    let centroid_bounds = primitives
+        .iter()
+        .map(|p| AABB::new(p.centroid, p.centroid))
+        .reduce(AABB::surrounding_box)?;
+    let parent_area = bbox.surface_area();
+
+    let mut best: Option<(usize, usize, Float)> = None;
+    for axis in 0..3 {
+        let lo = axis_value(&centroid_bounds.min(), axis);
+        let extent = axis_value(&centroid_bounds.max(), axis) - lo;
+        if extent <= 0.0 {
+            continue;
+        }
+        let bin_of = |p: &Primitive| {
+            let offset = (axis_value(&p.centroid, axis) - lo) / extent;
+            ((offset * BIN_COUNT as Float) as usize).min(BIN_COUNT - 1)
         };
 
-        let len = src_objects.len();
-
-        let (left, right) = match len {
-            0 => panic!("No objects to construct BvhNode"),
-            1 => (objects[0].clone(), objects[0].clone()),
-            2 => (objects[0].clone(), objects[1].clone()),
-            _ => {
-                objects.sort_by(comparator);
-                let mid = len / 2;
-                let (first, second) = objects.split_at(mid);
-                (
-                    Arc::new(BvhNode::new(first, time0, time1)) as Arc<dyn Hittable>,
-                    Arc::new(BvhNode::new(second, time0, time1)) as Arc<dyn Hittable>,
-                )
+        let mut counts = [0usize; BIN_COUNT];
+        let mut boxes: [Option<AABB>; BIN_COUNT] = [None; BIN_COUNT];
+        for p in primitives.iter() {
+            let bin = bin_of(p);
+            counts[bin] += 1;
+            boxes[bin] = Some(boxes[bin].map_or(p.bbox, |b| AABB::surrounding_box(b, p.bbox)));
+        }
+
+        // sweep from the right to get the area and count of every right side
+        let mut right_area = [0.0; BIN_COUNT];
+        let mut right_count = [0usize; BIN_COUNT];
+        let mut accum: Option<AABB> = None;
+        let mut count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            accum = merge(accum, boxes[bin]);
+            count += counts[bin];
+            right_area[bin] = accum.map_or(0.0, |b| b.surface_area());
+            right_count[bin] = count;
+        }
+
+        let mut accum: Option<AABB> = None;
+        let mut left_count = 0;
+        for split in 1..BIN_COUNT {
+            accum = merge(accum, boxes[split - 1]);
+            left_count += counts[split - 1];
+            if left_count == 0 || right_count[split] == 0 {
+                continue;
             }
+            let left_area = accum.map_or(0.0, |b| b.surface_area());
+            let cost = TRAVERSAL_COST
+                + (left_area * left_count as Float
+                    + right_area[split] * right_count[split] as Float)
+                    / parent_area;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, left_count, cost));
+            }
+        }
+    }
+
+    let (axis, left_count, cost) = best?;
+    // order by bin, ties broken by the previous order, to keep builds stable
+    let lo = axis_value(&centroid_bounds.min(), axis);
+    let extent = axis_value(&centroid_bounds.max(), axis) - lo;
+    primitives.sort_by_key(|p| {
+        let offset = (axis_value(&p.centroid, axis) - lo) / extent;
+        ((offset * BIN_COUNT as Float) as usize).min(BIN_COUNT - 1)
+    });
+    Some(Split {
+        axis,
+        left_count,
+        cost,
+    })
+}
+
+fn merge(a: Option<AABB>, b: Option<AABB>) -> Option<AABB> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(AABB::surrounding_box(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// A node of the flattened tree. Interior nodes are followed directly by
+/// their first child, leaves reference a range of `BvhNode::primitives`.
+#[derive(Clone, Copy)]
+enum LinearNode {
+    Interior {
+        bbox: AABB,
+        second_child: u32,
+        axis: u8,
+    },
+    Leaf {
+        bbox: AABB,
+        first: u32,
+        count: u32,
+    },
+}
+
+impl LinearNode {
+    fn bbox(&self) -> &AABB {
+        match self {
+            Self::Interior { bbox, .. } | Self::Leaf { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// Slab test with the reciprocal ray direction computed once per ray.
+fn hit_bbox(
+    bbox: &AABB,
+    origin: &[Float; 3],
+    inv_dir: &[Float; 3],
+    t_min: Float,
+    t_max: Float,
+) -> bool {
+    let min: [Float; 3] = bbox.min().into();
+    let max: [Float; 3] = bbox.max().into();
+    let mut t_min = t_min;
+    let mut t_max = t_max;
+    for i in 0..3 {
+        let mut t0 = (min[i] - origin[i]) * inv_dir[i];
+        let mut t1 = (max[i] - origin[i]) * inv_dir[i];
+        if inv_dir[i] < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_min = if t0 > t_min { t0 } else { t_min };
+        t_max = if t1 < t_max { t1 } else { t_max };
+        if t_max <= t_min {
+            return false;
+        }
+    }
+    true
+}
+
+/// A bounding volume hierarchy stored as a flat array of nodes in depth
+/// first order, traversed iteratively and nearest child first.
+pub struct BvhNode {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Arc<dyn Hittable>>,
+    has_media: bool,
+    /// Most interior nodes above a leaf, which bounds the traversal stack.
+    depth: usize,
+}
+
+impl BvhNode {
+    /// Builds a BVH with at most 4 primitives per leaf.
+    pub fn new(src_objects: &[Arc<dyn Hittable>], time0: Float, time1: Float) -> BvhNode {
+        Self::with_leaf_size(src_objects, time0, time1, 4)
+    }
+
+    /// Builds a BVH using the surface area heuristic over binned centroids.
+    /// Nodes with at most `max_leaf_size` primitives become leaves when that
+    /// is cheaper than splitting them. The same input always produces the
+    /// same tree.
+    pub fn with_leaf_size(
+        src_objects: &[Arc<dyn Hittable>],
+        time0: Float,
+        time1: Float,
+        max_leaf_size: usize,
+    ) -> BvhNode {
+        if src_objects.is_empty() {
+            panic!("No objects to construct BvhNode");
+        }
+        let primitives: Vec<_> = src_objects
+            .iter()
+            .map(|object| {
+                let mut bbox = AABB::default();
+                if !object.bounding_box(time0, time1, &mut bbox) {
+                    eprintln!("No bouding box in bvh_node constructor.");
+                }
+                Primitive {
+                    object: object.clone(),
+                    bbox,
+                    centroid: bbox.centroid(),
+                }
+            })
+            .collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            primitives: Vec::with_capacity(primitives.len()),
+            has_media: src_objects.iter().any(|object| object.has_media()),
+            depth: 0,
         };
+        bvh.build(primitives, max_leaf_size.max(1), 0);
+        bvh
+    }
 
-        let mut box_left = AABB::default();
-        let mut box_right = AABB::default();
-        if !left.bounding_box(time0, time1, &mut box_left)
-            || !right.bounding_box(time0, time1, &mut box_right)
+    fn build(&mut self, mut primitives: Vec<Primitive>, max_leaf_size: usize, depth: usize) {
+        let bbox = enclose(&primitives);
+        let split = if primitives.len() > 1 {
+            find_split(&mut primitives, &bbox)
+        } else {
+            None
+        };
+        let count = primitives.len();
+        if count == 1
+            || (count <= max_leaf_size && split.is_none_or(|split| split.cost >= count as Float))
         {
-            eprintln!("No bouding box in bvh_node constructor.");
+            self.nodes.push(LinearNode::Leaf {
+                bbox,
+                first: self.primitives.len() as u32,
+                count: count as u32,
+            });
+            self.primitives
+                .extend(primitives.into_iter().map(|p| p.object));
+            self.depth = self.depth.max(depth);
+            return;
+        }
+
+        // without a split all centroids coincide, and any partition is as
+        // good as another
+        let (axis, mid) = split.map_or((0, count / 2), |split| (split.axis, split.left_count));
+        let right = primitives.split_off(mid);
+
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode::Interior {
+            bbox,
+            second_child: 0,
+            axis: axis as u8,
+        });
+        self.build(primitives, max_leaf_size, depth + 1);
+        let second = self.nodes.len() as u32;
+        if let LinearNode::Interior { second_child, .. } = &mut self.nodes[index] {
+            *second_child = second;
         }
+        self.build(right, max_leaf_size, depth + 1);
+    }
 
-        Self {
-            bbox: AABB::surrounding_box(box_left, box_right),
-            left,
-            right,
+    /// A traversal stack that fits the tree: `inline` for usual depths,
+    /// `heap` grown to the depth of degenerate trees, such as those of
+    /// objects at exponentially growing distances.
+    fn stack<'s>(
+        &self,
+        inline: &'s mut [u32; INLINE_STACK_DEPTH],
+        heap: &'s mut Vec<u32>,
+    ) -> &'s mut [u32] {
+        if self.depth <= INLINE_STACK_DEPTH {
+            inline
+        } else {
+            heap.resize(self.depth, 0);
+            heap
         }
     }
 }
@@ -66,18 +291,209 @@ impl Hittable for BvhNode {
     where
         'a: 'b,
     {
-        if !self.bbox.hit(ray, t_min, t_max) {
-            return false;
+        let origin: [Float; 3] = ray.origin().into();
+        let inv_dir: [Float; 3] = ray.direction().apply(|d| 1.0 / d).into();
+        let mut closest_so_far = t_max;
+        let mut hit_anything = false;
+
+        let (mut inline_stack, mut heap_stack) = ([0; INLINE_STACK_DEPTH], Vec::new());
+        let stack = self.stack(&mut inline_stack, &mut heap_stack);
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current as usize];
+            if hit_bbox(node.bbox(), &origin, &inv_dir, t_min, closest_so_far) {
+                match *node {
+                    LinearNode::Leaf { first, count, .. } => {
+                        for object in &self.primitives[first as usize..(first + count) as usize] {
+                            if object.hit(ray, t_min, closest_so_far, rec) {
+                                hit_anything = true;
+                                closest_so_far = rec.t;
+                            }
+                        }
+                    }
+                    LinearNode::Interior {
+                        second_child, axis, ..
+                    } => {
+                        // visit the child on the side the ray comes from first
+                        let (near, far) = if inv_dir[axis as usize] < 0.0 {
+                            (second_child, current + 1)
+                        } else {
+                            (current + 1, second_child)
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        current = near;
+                        continue;
+                    }
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
         }
-        let hit_left = self.left.hit(ray, t_min, t_max, rec);
-        let hit_right = self
-            .right
-            .hit(ray, t_min, if hit_left { rec.t } else { t_max }, rec);
-        hit_left || hit_right
+
+        hit_anything
     }
 
     fn bounding_box(&self, _time0: Float, _time1: Float, output_box: &mut AABB) -> bool {
-        *output_box = self.bbox;
+        *output_box = *self.nodes[0].bbox();
         true
     }
+
+    fn has_media(&self) -> bool {
+        self.has_media
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        if !self.has_media {
+            return transmittance;
+        }
+        let origin: [Float; 3] = ray.origin().into();
+        let inv_dir: [Float; 3] = ray.direction().apply(|d| 1.0 / d).into();
+
+        let (mut inline_stack, mut heap_stack) = ([0; INLINE_STACK_DEPTH], Vec::new());
+        let stack = self.stack(&mut inline_stack, &mut heap_stack);
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current as usize];
+            if hit_bbox(node.bbox(), &origin, &inv_dir, t_min, t_max) {
+                match *node {
+                    LinearNode::Leaf { first, count, .. } => {
+                        for object in &self.primitives[first as usize..(first + count) as usize] {
+                            if object.has_media() {
+                                transmittance =
+                                    transmittance * object.transmittance(ray, t_min, t_max);
+                            }
+                        }
+                    }
+                    LinearNode::Interior { second_child, .. } => {
+                        stack[stack_len] = second_child;
+                        stack_len += 1;
+                        current += 1;
+                        continue;
+                    }
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+
+        transmittance
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.primitives {
+            if object.is_emissive() {
+                lights.add_unique(object.clone());
+            } else {
+                object.collect_lights(lights);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConstantMedium, Lambertian, Material, Rng, SolidColor, Sphere};
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_with_color(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn random_point(rng: &mut Rng, extent: Float) -> Point3 {
+        Point3::new(
+            rng.range(-extent..extent),
+            rng.range(-extent..extent),
+            rng.range(-extent..extent),
+        )
+    }
+
+    /// The distance to the closest hit of `ray` in `world`.
+    fn closest(world: &impl Hittable, ray: &Ray) -> Option<Float> {
+        let mut rec = HitRecord::default();
+        world
+            .hit(ray, 0.001, Float::INFINITY, &mut rec)
+            .then_some(rec.t)
+    }
+
+    #[test]
+    fn finds_the_closest_hit_of_a_list() {
+        let mut rng = Rng::new(1, 0);
+        let material = gray();
+        let spheres: Vec<Arc<dyn Hittable>> = (0..200)
+            .map(|_| {
+                let center = random_point(&mut rng, 10.0);
+                let radius = rng.range(0.1..1.0);
+                Arc::new(Sphere::new(center, radius, material.clone())) as _
+            })
+            .collect();
+        let mut list = HittableList::new();
+        for sphere in &spheres {
+            list.add_shared(sphere.clone());
+        }
+
+        for leaf_size in [1, 4, 16] {
+            let bvh = BvhNode::with_leaf_size(&spheres, 0.0, 1.0, leaf_size);
+            let mut hits = 0;
+            for _ in 0..500 {
+                let origin = random_point(&mut rng, 12.0);
+                let ray = Ray::new(origin, random_point(&mut rng, 5.0) - origin, 0.0);
+                let expected = closest(&list, &ray);
+                assert_eq!(closest(&bvh, &ray), expected, "{leaf_size}");
+                hits += expected.is_some() as usize;
+            }
+            assert!(hits > 100, "{hits}");
+        }
+    }
+
+    /// Spheres at exponentially growing distances along +x, which the
+    /// surface area heuristic splits off one at a time.
+    fn chain(count: i32, make: impl Fn(Arc<dyn Hittable>) -> Arc<dyn Hittable>) -> BvhNode {
+        let material = gray();
+        let spheres: Vec<Arc<dyn Hittable>> = (0..count)
+            .map(|i| {
+                let center = Point3::new((2.0 as Float).powi(i), 0.0, 0.0);
+                make(Arc::new(Sphere::new(center, 0.25, material.clone())))
+            })
+            .collect();
+        BvhNode::with_leaf_size(&spheres, 0.0, 1.0, 1)
+    }
+
+    #[test]
+    fn traverses_trees_deeper_than_the_inline_stack() {
+        let bvh = chain(300, |sphere| sphere);
+        assert!(bvh.depth > INLINE_STACK_DEPTH, "{}", bvh.depth);
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(closest(&bvh, &ray), Some(1.75));
+    }
+
+    #[test]
+    fn attenuates_through_trees_deeper_than_the_inline_stack() {
+        let density = 0.01;
+        let bvh = chain(300, |sphere| {
+            Arc::new(ConstantMedium::new(
+                sphere,
+                density,
+                Arc::new(SolidColor::new(1.0, 1.0, 1.0)),
+            ))
+        });
+        assert!(bvh.depth > INLINE_STACK_DEPTH, "{}", bvh.depth);
+        // stop short of the spheres too far out to intersect precisely
+        let ray = Ray::new_shadow(Point3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let transmittance = bvh.transmittance(&ray, 0.001, (2.0 as Float).powi(20) - 10.0);
+        let expected = (-density * 0.5 * 20.0).exp();
+        assert!(
+            (transmittance.x() - expected).abs() < 1e-9,
+            "{transmittance:?}"
+        );
+    }
 }
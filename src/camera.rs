@@ -1,6 +1,4 @@
-use rand::random;
-
-use crate::{Float, Point3, Ray, Vec3, PI};
+use crate::{Float, Point3, Ray, Sampler, Vec3, PI};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -16,6 +14,7 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -54,14 +53,58 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: Float, t: Float) -> Ray {
-        let (sin, cos) = (random::<Float>() * PI * 2.0).sin_cos();
-        let offset = random::<Float>() * self.lens_radius * (cos * self.u + sin * self.v);
+    pub fn get_ray(&self, s: Float, t: Float, sampler: &mut dyn Sampler) -> Ray {
+        let (r1, r2) = sampler.get_2d();
+        let (sin, cos) = (r1 * PI * 2.0).sin_cos();
+        let offset = r2 * self.lens_radius * (cos * self.u + sin * self.v);
 
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            random::<Float>() * (self.time1 - self.time0) + self.time0,
+            self.time0 + sampler.get_1d() * (self.time1 - self.time0),
         )
     }
 }
+
+#[derive(Debug, Clone, Copy)]
+pub struct CameraConfig {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: Float,
+    pub aperture: Float,
+    pub focus_dist: Float,
+    pub time0: Float,
+    pub time1: Float,
+}
+
+impl CameraConfig {
+    pub fn build(&self, aspect_ratio: Float) -> Camera {
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            lookfrom: Point3::new(13.0, 2.0, 3.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: 10.0,
+            time0: 0.0,
+            time1: 1.0,
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use rand::random;
-
-use crate::{Float, HitRecord, Hittable, Isotropic, Material, Ray, Texture, Vec3, AABB};
+use crate::{
+    Color, Float, HitRecord, Hittable, Isotropic, Material, Ray, Rng, Texture, Vec3, AABB,
+};
 
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
@@ -12,10 +12,24 @@ pub struct ConstantMedium {
 
 impl ConstantMedium {
     pub fn new(boundary: Arc<dyn Hittable>, density: Float, texture: Arc<dyn Texture>) -> Self {
+        Self::new_with_phase(
+            boundary,
+            density,
+            Arc::new(Isotropic::new_with_texture(texture)),
+        )
+    }
+
+    /// A medium scattering with `phase_function`, such as
+    /// `HenyeyGreenstein`, instead of isotropically.
+    pub fn new_with_phase(
+        boundary: Arc<dyn Hittable>,
+        density: Float,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
         Self {
             boundary,
             neg_inv_density: -1.0 / density,
-            phase_function: Arc::new(Isotropic::new_with_texture(texture)),
+            phase_function,
         }
     }
 }
@@ -29,6 +43,10 @@ impl Hittable for ConstantMedium {
     where
         'a: 'b,
     {
+        // shadow rays pass through and are attenuated by `transmittance`
+        if ray.is_shadow() {
+            return false;
+        }
         let mut rec1 = HitRecord::default();
         let mut rec2 = HitRecord::default();
 
@@ -62,7 +80,9 @@ impl Hittable for ConstantMedium {
 
         let ray_length = ray.direction().length();
         let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let hit_distance = self.neg_inv_density * random::<Float>().ln();
+        // `hit` has no generator, so the free path is drawn from one
+        // derived from the ray and the seed the path gave it
+        let hit_distance = self.neg_inv_density * Rng::for_ray(ray).float().ln();
 
         if hit_distance > distance_inside_boundary {
             return false;
@@ -77,4 +97,29 @@ impl Hittable for ConstantMedium {
 
         true
     }
+
+    fn has_media(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        let white = Color::new(1.0, 1.0, 1.0);
+        if !ray.is_shadow() {
+            return white;
+        }
+        let mut rec1 = HitRecord::default();
+        let mut rec2 = HitRecord::default();
+        if !self
+            .boundary
+            .hit(ray, -Float::INFINITY, Float::INFINITY, &mut rec1)
+            || !self
+                .boundary
+                .hit(ray, rec1.t + 0.0001, Float::INFINITY, &mut rec2)
+        {
+            return white;
+        }
+        let distance = (rec2.t.min(t_max) - rec1.t.max(t_min)).max(0.0) * ray.direction().length();
+        let transmittance = (distance / self.neg_inv_density).exp();
+        Color::new(transmittance, transmittance, transmittance)
+    }
 }
@@ -0,0 +1,121 @@
+use crate::{Float, Perlin, Point3, AABB};
+
+/// A scalar field giving the density of a heterogeneous medium at every
+/// point, in the space of the medium's boundary.
+pub trait DensityField: Sync + Send {
+    fn density(&self, p: &Point3) -> Float;
+
+    /// An upper bound of `density`, which the tracking estimators use as
+    /// their majorant.
+    fn max_density(&self) -> Float;
+}
+
+/// Density from Perlin turbulence, e.g. for clouds and smoke.
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: Float,
+    depth: u32,
+}
+
+impl NoiseDensity {
+    pub fn new(scale: Float, depth: u32) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            depth,
+        }
+    }
+}
+
+impl DensityField for NoiseDensity {
+    fn density(&self, p: &Point3) -> Float {
+        self.noise
+            .turb(&(self.scale * *p), self.depth)
+            .min(self.max_density())
+    }
+
+    fn max_density(&self) -> Float {
+        // every octave adds at most half of the previous one
+        2.0 * (1.0 - (0.5 as Float).powi(self.depth as i32))
+    }
+}
+
+/// Density from a dense grid of samples at the centers of the cells that
+/// divide `bbox`, interpolated trilinearly and zero outside of it.
+pub struct VoxelGrid {
+    values: Vec<Float>,
+    dimensions: [usize; 3],
+    bbox: AABB,
+    max_density: Float,
+}
+
+impl VoxelGrid {
+    /// `values` holds `dimensions[0]` samples along x for every y, and
+    /// those rows for every z. Negative samples, which signed formats can
+    /// hold, are taken as empty.
+    pub fn new(mut values: Vec<Float>, dimensions: [usize; 3], bbox: AABB) -> Self {
+        assert_eq!(
+            values.len(),
+            dimensions.iter().product::<usize>(),
+            "voxel count does not match the grid dimensions"
+        );
+        for value in &mut values {
+            *value = value.max(0.0);
+        }
+        let max_density = values.iter().copied().fold(0.0, Float::max);
+        Self {
+            values,
+            dimensions,
+            bbox,
+            max_density,
+        }
+    }
+
+    pub const fn dimensions(&self) -> [usize; 3] {
+        self.dimensions
+    }
+
+    pub const fn bbox(&self) -> AABB {
+        self.bbox
+    }
+
+    fn value(&self, x: usize, y: usize, z: usize) -> Float {
+        let [nx, ny, _] = self.dimensions;
+        self.values[(z * ny + y) * nx + x]
+    }
+}
+
+impl DensityField for VoxelGrid {
+    fn density(&self, p: &Point3) -> Float {
+        let min: [Float; 3] = self.bbox.min().into();
+        let max: [Float; 3] = self.bbox.max().into();
+        let p: [Float; 3] = (*p).into();
+
+        let mut cells = [(0, 0, 0.0); 3];
+        for axis in 0..3 {
+            if p[axis] < min[axis] || p[axis] > max[axis] {
+                return 0.0;
+            }
+            let n = self.dimensions[axis];
+            let offset = (p[axis] - min[axis]) / (max[axis] - min[axis]) * n as Float - 0.5;
+            let offset = offset.clamp(0.0, (n - 1) as Float);
+            let lower = (offset as usize).min(n.saturating_sub(2));
+            cells[axis] = (lower, (lower + 1).min(n - 1), offset - lower as Float);
+        }
+
+        let [(x0, x1, tx), (y0, y1, ty), (z0, z1, tz)] = cells;
+        let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+        let plane = |z: usize| {
+            lerp(
+                lerp(self.value(x0, y0, z), self.value(x1, y0, z), tx),
+                lerp(self.value(x0, y1, z), self.value(x1, y1, z), tx),
+                ty,
+            )
+        };
+        lerp(plane(z0), plane(z1), tz)
+    }
+
+    fn max_density(&self) -> Float {
+        self.max_density
+    }
+}
@@ -0,0 +1,115 @@
+use crate::Float;
+
+/// A piecewise constant distribution over [0, 1) with one piece per value
+/// of a non-negative function, for importance sampling tabulated data.
+pub struct Distribution1D {
+    func: Vec<Float>,
+    cdf: Vec<Float>,
+    integral: Float,
+}
+
+impl Distribution1D {
+    /// # Panics
+    ///
+    /// If `func` is empty.
+    pub fn new(func: Vec<Float>) -> Self {
+        assert!(!func.is_empty(), "Distribution1D needs at least one value");
+        let n = func.len() as Float;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f.max(0.0) / n);
+        }
+        let integral = cdf[func.len()];
+        if integral > 0.0 {
+            for c in &mut cdf[1..] {
+                *c /= integral;
+            }
+        } else {
+            // nothing to prefer, sample uniformly
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as Float / n;
+            }
+        }
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// The integral of the function over [0, 1).
+    pub fn integral(&self) -> Float {
+        self.integral
+    }
+
+    /// Maps a uniform `u` to a sample `x` in [0, 1). Returns `x`, its
+    /// density and the index of the piece it falls in.
+    pub fn sample(&self, u: Float) -> (Float, Float, usize) {
+        // the last cdf entry not above `u`
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.count() - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as Float + du) / self.count() as Float).min(1.0 - Float::EPSILON);
+        (x, self.pdf(offset), offset)
+    }
+
+    /// The density of piece `index`.
+    pub fn pdf(&self, index: usize) -> Float {
+        if self.integral > 0.0 {
+            self.func[index].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise constant distribution over [0, 1)², given row by row, that
+/// samples a row from the marginal distribution and then a column within
+/// it.
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// # Panics
+    ///
+    /// If `func` is empty or its length is not a multiple of `width`.
+    pub fn new(func: &[Float], width: usize) -> Self {
+        assert!(
+            width > 0 && !func.is_empty() && func.len().is_multiple_of(width),
+            "Distribution2D needs whole rows"
+        );
+        let rows: Vec<_> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|r| r.integral()).collect());
+        Self { rows, marginal }
+    }
+
+    /// Maps uniform (`u`, `v`) to a point, `u` along a row and `v` across
+    /// rows. Returns the point and its density.
+    pub fn sample(&self, u: Float, v: Float) -> ((Float, Float), Float) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.rows[row].sample(u);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    /// The density at point (`x`, `y`) of [0, 1)².
+    pub fn pdf(&self, x: Float, y: Float) -> Float {
+        let row = ((y * self.rows.len() as Float) as usize).min(self.rows.len() - 1);
+        let columns = &self.rows[row];
+        let column = ((x * columns.count() as Float) as usize).min(columns.count() - 1);
+        columns.pdf(column) * self.marginal.pdf(row)
+    }
+}
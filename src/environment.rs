@@ -0,0 +1,363 @@
+use crate::{
+    tone_map::{luminance, xyz_to_rgb},
+    Color, Distribution2D, Float, Matrix4, Onb, Sampler, Vec3, PI,
+};
+
+/// The light arriving from infinitely far away, seen by rays that leave the
+/// scene.
+pub trait Environment: Sync + Send {
+    /// Radiance arriving from `direction`, a unit vector pointing away from
+    /// the scene.
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    /// Density, over solid angle, of `random` returning `direction`. The
+    /// default samples the whole sphere uniformly.
+    fn pdf_value(&self, _direction: &Vec3) -> Float {
+        1.0 / (4.0 * PI)
+    }
+
+    /// A unit direction towards the environment, preferably a bright part.
+    fn random(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        uniform_sphere(sampler)
+    }
+
+    /// Whether `radiance` can be non-zero, so that the environment is worth
+    /// sampling as a light.
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+pub(crate) fn uniform_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let (r1, r2) = sampler.get_2d();
+    let z = 1.0 - 2.0 * r1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let (sin, cos) = (2.0 * PI * r2).sin_cos();
+    Vec3::new(r * cos, r * sin, z)
+}
+
+/// The same radiance from every direction.
+pub struct ConstantEnvironment(Color);
+
+impl ConstantEnvironment {
+    pub fn new(color: Color) -> Self {
+        Self(color)
+    }
+}
+
+impl From<Color> for ConstantEnvironment {
+    fn from(color: Color) -> Self {
+        Self(color)
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: &Vec3) -> Color {
+        self.0
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.0.x() > 0.0 || self.0.y() > 0.0 || self.0.z() > 0.0
+    }
+}
+
+/// A linear blend from `bottom`, straight down, to `top`, straight up.
+pub struct GradientSky {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientSky {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl Default for GradientSky {
+    /// The white to blue sky of the first book.
+    fn default() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for GradientSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let t = 0.5 * (direction.y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+/// Radiance units per kcd/m² of sky luminance, which puts the zenith of a
+/// clear daytime sky around 1.
+const SUN_SKY_SCALE: Float = 0.1;
+/// Luminance of the sun outside the atmosphere, in kcd/m².
+const SUN_LUMINANCE: Float = 2.1e6;
+/// Angular radius of the sun disk, in degrees.
+const SUN_RADIUS: Float = 0.2665;
+
+/// The analytic daylight model of Preetham, Shirley and Smits, "A
+/// Practical Analytic Model for Daylight": a clear sky whose brightness and
+/// color follow the sun, plus the sun disk seen through the atmosphere.
+/// `turbidity` measures haze, 2 is very clear and 10 hazy. The world is
+/// y up; below the horizon the sky keeps its color at the horizon, but is
+/// only reached by material sampling.
+pub struct SunSky {
+    sun: Vec3,
+    /// Perez coefficients A to E of the luminance Y and the chromaticities
+    /// x and y.
+    perez: [[Float; 5]; 3],
+    /// Zenith values of Y, x and y divided by the Perez function at the
+    /// zenith, so that scaling the function by them gives the sky value.
+    zenith: [Float; 3],
+    sun_radiance: Color,
+    intensity: Float,
+    cos_sun_radius: Float,
+    /// Probability of sampling the sun disk rather than the sky.
+    sun_probability: Float,
+}
+
+impl SunSky {
+    /// A sky lit by the sun in direction `sun`, with radiance scaled by
+    /// `intensity`.
+    pub fn new(sun: Vec3, turbidity: Float, intensity: Float) -> Self {
+        let sun = sun.unit_vector();
+        let t = turbidity.clamp(1.7, 10.0);
+        let theta_s = sun.y().clamp(-1.0, 1.0).acos();
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let chromaticity = |m: [[Float; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            [t * t, t, 1.0]
+                .iter()
+                .zip(m)
+                .map(|(tw, row)| tw * row.iter().zip(thetas).map(|(a, b)| a * b).sum::<Float>())
+                .sum::<Float>()
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_chroma_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let mut zenith = [zenith_y, zenith_x, zenith_chroma_y];
+        for (z, coefficients) in zenith.iter_mut().zip(&perez) {
+            *z /= perez_function(coefficients, 1.0, theta_s);
+        }
+
+        // sunlight dimmed by Rayleigh and aerosol scattering along its path
+        // through the atmosphere, at a representative wavelength (in
+        // micrometers) per channel
+        let sun_radiance = if sun.y() > 0.0 {
+            let relative_mass =
+                1.0 / (sun.y() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = |lambda: Float| {
+                let rayleigh = (-0.008735 * lambda.powf(-4.08) * relative_mass).exp();
+                let aerosol = (-beta * lambda.powf(-1.3) * relative_mass).exp();
+                rayleigh * aerosol
+            };
+            SUN_LUMINANCE
+                * SUN_SKY_SCALE
+                * intensity
+                * Color::new(
+                    transmittance(0.65),
+                    transmittance(0.55),
+                    transmittance(0.45),
+                )
+        } else {
+            Color::default()
+        };
+
+        let cos_sun_radius = SUN_RADIUS.to_radians().cos();
+        let mut sky = Self {
+            sun,
+            perez,
+            zenith,
+            sun_radiance,
+            intensity,
+            cos_sun_radius,
+            sun_probability: 0.0,
+        };
+        // split the samples by a rough estimate of the light each part
+        // casts on the ground
+        let sun_power =
+            luminance(&sun_radiance) * 2.0 * PI * (1.0 - cos_sun_radius) * sun.y().max(0.0);
+        let sky_power = luminance(&sky.sky_radiance(&Vec3::new(0.0, 1.0, 0.0))) * PI;
+        if sun_power > 0.0 {
+            sky.sun_probability = (sun_power / (sun_power + sky_power)).min(0.9);
+        }
+        sky
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let gamma = direction.dot(&self.sun).clamp(-1.0, 1.0).acos();
+        let [y, x, chroma_y] = [0, 1, 2]
+            .map(|i| self.zenith[i] * perez_function(&self.perez[i], direction.y(), gamma));
+        if chroma_y <= 0.0 {
+            return Color::default();
+        }
+        let xyz = Vec3::new(x / chroma_y * y, y, (1.0 - x - chroma_y) / chroma_y * y);
+        (SUN_SKY_SCALE * self.intensity * xyz_to_rgb(&xyz)).apply(|c| c.max(0.0))
+    }
+
+    fn sun_cone_pdf(&self) -> Float {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+/// The Perez sky luminance distribution for a direction at `cos_theta` from
+/// the zenith and `gamma` radians from the sun.
+fn perez_function(c: &[Float; 5], cos_theta: Float, gamma: Float) -> Float {
+    let cos_gamma = gamma.cos();
+    (1.0 + c[0] * (c[1] / cos_theta.max(1e-3)).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+impl Environment for SunSky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let mut radiance = self.sky_radiance(direction);
+        if direction.dot(&self.sun) >= self.cos_sun_radius && direction.y() > 0.0 {
+            radiance += self.sun_radiance;
+        }
+        radiance
+    }
+
+    /// A mixture of the sun cone and a cosine weighted upper hemisphere.
+    fn pdf_value(&self, direction: &Vec3) -> Float {
+        let mut pdf = (1.0 - self.sun_probability) * direction.y().max(0.0) / PI;
+        if direction.dot(&self.sun) >= self.cos_sun_radius {
+            pdf += self.sun_probability * self.sun_cone_pdf();
+        }
+        pdf
+    }
+
+    fn random(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        if sampler.get_1d() < self.sun_probability {
+            let (r1, r2) = sampler.get_2d();
+            let z = 1.0 - r1 * (1.0 - self.cos_sun_radius);
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let (sin, cos) = (2.0 * PI * r2).sin_cos();
+            Onb::from(&self.sun).local(r * cos, r * sin, z)
+        } else {
+            Onb::from(&Vec3::new(0.0, 1.0, 0.0)).local_v(&Vec3::random_cosine_direction(sampler))
+        }
+    }
+}
+
+/// An equirectangular (latitude-longitude) image of the surroundings, with
+/// +y at the top row and the same longitudes as the texture coordinates of
+/// a `Sphere`, optionally rotated about the y axis. Directions are
+/// importance sampled in proportion to the brightness of the pixels.
+pub struct EnvironmentMap {
+    pixels: Vec<Color>,
+    width: usize,
+    height: usize,
+    to_world: Matrix4,
+    to_local: Matrix4,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `pixels` row by row from the top, `rotation` in degrees about +y.
+    ///
+    /// # Panics
+    ///
+    /// If `pixels` does not hold whole rows of `width`.
+    pub fn new(pixels: Vec<Color>, width: u32, rotation: Float) -> Self {
+        let width = width as usize;
+        assert!(
+            width > 0 && !pixels.is_empty() && pixels.len().is_multiple_of(width),
+            "EnvironmentMap needs whole rows"
+        );
+        let height = pixels.len() / width;
+        // rows near the poles cover less solid angle
+        let func: Vec<Float> = pixels
+            .chunks(width)
+            .enumerate()
+            .flat_map(|(j, row)| {
+                let sin_theta = (PI * (j as Float + 0.5) / height as Float).sin();
+                row.iter().map(move |c| luminance(c).max(0.0) * sin_theta)
+            })
+            .collect();
+        let to_world = Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), rotation);
+        Self {
+            distribution: Distribution2D::new(&func, width),
+            pixels,
+            width,
+            height,
+            to_world,
+            to_local: to_world.transpose(),
+        }
+    }
+
+    /// Image coordinates in [0, 1)² of a direction, and the sine of its
+    /// angle to +y.
+    fn uv(&self, direction: &Vec3) -> ((Float, Float), Float) {
+        let d = self.to_local.transform_vector(direction).unit_vector();
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = (-d.z()).atan2(d.x()) + PI;
+        ((phi / (2.0 * PI), theta / PI), theta.sin())
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let ((u, v), _) = self.uv(direction);
+        let i = ((u * self.width as Float) as usize).min(self.width - 1);
+        let j = ((v * self.height as Float) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+
+    fn pdf_value(&self, direction: &Vec3) -> Float {
+        let ((u, v), sin_theta) = self.uv(direction);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // from density over the image to density over solid angle
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let ((u, v), _) = self.distribution.sample(r1, r2);
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+        self.to_world.transform_vector(&Vec3::new(
+            -cos_phi * sin_theta,
+            cos_theta,
+            sin_phi * sin_theta,
+        ))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.pixels.iter().any(|c| luminance(c) > 0.0)
+    }
+}
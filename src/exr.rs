@@ -0,0 +1,146 @@
+use std::io::{self, Write};
+
+use crate::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+impl ExrPixelType {
+    const fn id(self) -> i32 {
+        match self {
+            Self::Half => 1,
+            Self::Float => 2,
+        }
+    }
+
+    const fn size(self) -> usize {
+        match self {
+            Self::Half => 2,
+            Self::Float => 4,
+        }
+    }
+}
+
+/// Converts to IEEE 754 binary16, rounding to nearest even.
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = rest > halfway || (rest == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + u32::from(round)) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1fff;
+    let round = rest > 0x1000 || (rest == 0x1000 && half & 1 == 1);
+    // a carry out of the mantissa correctly bumps the exponent, up to infinity
+    sign | (half + u32::from(round)) as u16
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Writes linear radiance, row by row from the top, as an uncompressed
+/// single part scanline OpenEXR image with R, G and B channels.
+pub fn write_exr(
+    image: &[Color],
+    width: u32,
+    height: u32,
+    pixel_type: ExrPixelType,
+    mut w: impl Write,
+) -> io::Result<()> {
+    assert_eq!(image.len(), (width * height) as usize);
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+    let mut channels = Vec::new();
+    // channels must be sorted by name
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&pixel_type.id().to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let line_size = width as usize * 3 * pixel_type.size();
+    let block_size = (8 + line_size) as u64;
+    let first_block = (header.len() + height as usize * 8) as u64;
+    for y in 0..height as u64 {
+        header.extend_from_slice(&(first_block + y * block_size).to_le_bytes());
+    }
+    w.write_all(&header)?;
+
+    let mut line = Vec::with_capacity(line_size);
+    for (y, row) in image.chunks(width as usize).enumerate() {
+        line.clear();
+        for channel in [Color::z, Color::y, Color::x] {
+            for color in row {
+                let value = channel(color) as f32;
+                match pixel_type {
+                    ExrPixelType::Half => line.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    ExrPixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        w.write_all(&(y as i32).to_le_bytes())?;
+        w.write_all(&(line_size as i32).to_le_bytes())?;
+        w.write_all(&line)?;
+    }
+    w.flush()
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{Float, HitRecord, Hittable, AABB};
+use crate::{Color, Float, HitRecord, Hittable, HittableList, Point3, Ray, Sampler, Vec3, AABB};
 
 pub struct FlipFace {
     ptr: Arc<dyn Hittable>,
@@ -13,13 +13,7 @@ impl FlipFace {
 }
 
 impl Hittable for FlipFace {
-    fn hit<'a, 'b>(
-        &'a self,
-        ray: &crate::Ray,
-        t_min: Float,
-        t_max: Float,
-        rec: &mut HitRecord<'b>,
-    ) -> bool
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
     where
         'a: 'b,
     {
@@ -34,4 +28,28 @@ impl Hittable for FlipFace {
     fn bounding_box(&self, time0: Float, time1: Float, output_box: &mut AABB) -> bool {
         self.ptr.bounding_box(time0, time1, output_box)
     }
+
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+        self.ptr.pdf_value(o, v)
+    }
+
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.ptr.random(o, sampler)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.ptr.is_emissive()
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.ptr.collect_lights(lights);
+    }
+
+    fn has_media(&self) -> bool {
+        self.ptr.has_media()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        self.ptr.transmittance(ray, t_min, t_max)
+    }
 }
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use crate::{
+    BlockBox, Color, EmptyMaterial, Float, HeterogeneousMedium, HitRecord, Hittable, Matrix4, Ray,
+    Transform, VoxelGrid, AABB,
+};
+
+/// A medium whose density comes from a voxel grid, such as the clouds and
+/// smoke of simulations, filling the box of the grid and placed in the
+/// world by `transform`.
+pub struct GridMedium {
+    inner: Transform,
+}
+
+impl GridMedium {
+    /// The absorption and scattering coefficients are per unit of length in
+    /// the space of the grid, before `transform`. `anisotropy` is the
+    /// asymmetry of the Henyey-Greenstein phase function, 0 for isotropic.
+    ///
+    /// # Panics
+    ///
+    /// If `transform` is not invertible.
+    pub fn new(
+        grid: VoxelGrid,
+        sigma_a: Color,
+        sigma_s: Color,
+        anisotropy: Float,
+        transform: Matrix4,
+    ) -> Self {
+        let bbox = grid.bbox();
+        let boundary = Arc::new(BlockBox::new(
+            bbox.min(),
+            bbox.max(),
+            Arc::new(EmptyMaterial),
+        ));
+        let medium = HeterogeneousMedium::new(boundary, Arc::new(grid), sigma_a, sigma_s)
+            .with_anisotropy(anisotropy);
+        Self {
+            inner: Transform::new(Arc::new(medium), transform),
+        }
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        self.inner.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float, output_box: &mut AABB) -> bool {
+        self.inner.bounding_box(time0, time1, output_box)
+    }
+
+    fn has_media(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        self.inner.transmittance(ray, t_min, t_max)
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{Color, Float};
+
+/// Converts a color to the shared exponent RGBE encoding of Radiance files.
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let v = color.x().max(color.y()).max(color.z());
+    if v.is_nan() || v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let v = v.min(1e38);
+    let mut exponent = v.log2().floor() as i32 + 1;
+    // guard against rounding in log2 so that the mantissa is in [0.5, 1)
+    let mut mantissa = v / (2.0 as Float).powi(exponent);
+    if mantissa >= 1.0 {
+        exponent += 1;
+        mantissa *= 0.5;
+    } else if mantissa < 0.5 {
+        exponent -= 1;
+        mantissa *= 2.0;
+    }
+    let scale = mantissa * 256.0 / v;
+    let channel = |c: Float| (c.max(0.0) * scale) as u8;
+    [
+        channel(color.x()),
+        channel(color.y()),
+        channel(color.z()),
+        (exponent + 128) as u8,
+    ]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::default();
+    }
+    let scale = (2.0 as Float).powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        rgbe[0] as Float * scale,
+        rgbe[1] as Float * scale,
+        rgbe[2] as Float * scale,
+    )
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads one scanline, either flat or run length encoded per component.
+fn read_scanline(r: &mut impl BufRead, width: usize, line: &mut Vec<[u8; 4]>) -> io::Result<()> {
+    let mut start = [0u8; 4];
+    r.read_exact(&mut start)?;
+    line.clear();
+    let encoded_width = ((start[2] as usize) << 8) | start[3] as usize;
+    if start[0] != 2 || start[1] != 2 || start[2] & 0x80 != 0 || !(8..0x8000).contains(&width) {
+        line.push(start);
+        for _ in 1..width {
+            let mut pixel = [0u8; 4];
+            r.read_exact(&mut pixel)?;
+            line.push(pixel);
+        }
+        return Ok(());
+    }
+    if encoded_width != width {
+        return Err(invalid_data("scanline width mismatch"));
+    }
+
+    line.resize(width, [0; 4]);
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            r.read_exact(&mut count)?;
+            let (run, count) = if count[0] > 128 {
+                (true, count[0] as usize - 128)
+            } else {
+                (false, count[0] as usize)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("bad scanline run"));
+            }
+            if run {
+                let mut value = [0u8; 1];
+                r.read_exact(&mut value)?;
+                for pixel in &mut line[x..x + count] {
+                    pixel[component] = value[0];
+                }
+            } else {
+                let mut values = vec![0u8; count];
+                r.read_exact(&mut values)?;
+                for (pixel, value) in line[x..x + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image with the usual `-Y H +X W`
+/// orientation. Returns linear radiance row by row from the top, and the
+/// width and height.
+pub fn read_hdr(mut r: impl BufRead) -> io::Result<(Vec<Color>, u32, u32)> {
+    let mut line = String::new();
+    r.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("not a Radiance file"));
+    }
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Err(invalid_data("missing resolution"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data("only RGBE Radiance files are supported"));
+            }
+        }
+    }
+
+    line.clear();
+    r.read_line(&mut line)?;
+    let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => match (h.parse::<u32>(), w.parse::<u32>()) {
+            (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+            _ => return Err(invalid_data("bad resolution")),
+        },
+        _ => return Err(invalid_data("unsupported image orientation")),
+    };
+
+    let mut image = Vec::with_capacity((width * height) as usize);
+    let mut scanline = Vec::with_capacity(width as usize);
+    for _ in 0..height {
+        read_scanline(&mut r, width as usize, &mut scanline)?;
+        image.extend(scanline.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok((image, width, height))
+}
+
+/// Run length encodes one component of a scanline in the format of
+/// "new style" Radiance files.
+fn write_rle_component(data: &[u8], w: &mut impl Write) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+    let mut cur = 0;
+    while cur < data.len() {
+        let mut beg_run = cur;
+        let mut run_count = 0;
+        while run_count < MIN_RUN && beg_run < data.len() {
+            beg_run += run_count;
+            run_count = 1;
+            while beg_run + run_count < data.len()
+                && run_count < 127
+                && data[beg_run] == data[beg_run + run_count]
+            {
+                run_count += 1;
+            }
+        }
+        if run_count < MIN_RUN {
+            beg_run = data.len();
+        }
+
+        while cur < beg_run {
+            let count = (beg_run - cur).min(128);
+            w.write_all(&[count as u8])?;
+            w.write_all(&data[cur..cur + count])?;
+            cur += count;
+        }
+
+        if run_count >= MIN_RUN {
+            w.write_all(&[128 + run_count as u8, data[beg_run]])?;
+            cur += run_count;
+        }
+    }
+    Ok(())
+}
+
+/// Writes linear radiance, row by row from the top, as a Radiance RGBE
+/// (`.hdr`) image.
+pub fn write_hdr(image: &[Color], width: u32, height: u32, mut w: impl Write) -> io::Result<()> {
+    assert_eq!(image.len(), (width * height) as usize);
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let rle = (8..0x8000).contains(&width);
+    let mut components: [Vec<u8>; 4] = Default::default();
+    for row in image.chunks(width as usize) {
+        let pixels = row.iter().map(to_rgbe);
+        if !rle {
+            for pixel in pixels {
+                w.write_all(&pixel)?;
+            }
+            continue;
+        }
+
+        for component in &mut components {
+            component.clear();
+        }
+        for pixel in pixels {
+            for (component, byte) in components.iter_mut().zip(pixel) {
+                component.push(byte);
+            }
+        }
+        w.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for component in &components {
+            write_rle_component(component, &mut w)?;
+        }
+    }
+    w.flush()
+}
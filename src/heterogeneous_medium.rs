@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use rand::RngCore;
+
+use crate::{
+    rng::mix, Color, DensityField, Float, HenyeyGreenstein, HitRecord, Hittable, Isotropic,
+    Material, Ray, Rng, Vec3, AABB,
+};
+
+/// How many times a ray may enter a boundary, which bounds the work spent
+/// on boundaries that are not closed.
+const MAX_SEGMENTS: usize = 32;
+
+/// A participating medium inside `boundary` whose density varies with a
+/// `DensityField`. The boundary needs to be closed but not convex.
+///
+/// The absorption and scattering coefficients are per channel and scaled by
+/// the density. Collisions are sampled by delta tracking with the smallest
+/// extinction coefficient, and the part of the extinction that differs per
+/// channel is left to ratio tracking in `transmittance`, as is the whole of
+/// it for shadow rays.
+pub struct HeterogeneousMedium {
+    boundary: Arc<dyn Hittable>,
+    density: Arc<dyn DensityField>,
+    sigma_t: Color,
+    /// The smallest channel of `sigma_t`, with which collisions are sampled.
+    sigma_min: Float,
+    /// The albedo of the collisions sampled with `sigma_min`.
+    albedo: Color,
+    phase_function: Arc<dyn Material>,
+    /// Keeps the sequences of media crossed by the same ray independent.
+    salt: u64,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: Arc<dyn DensityField>,
+        sigma_a: Color,
+        sigma_s: Color,
+    ) -> Self {
+        let sigma_t = sigma_a + sigma_s;
+        let sigma_min = sigma_t.x().min(sigma_t.y()).min(sigma_t.z()).max(0.0);
+        // a collision sampled with `sigma_min` scatters `sigma_s / sigma_min`
+        let albedo = if sigma_min > 0.0 {
+            sigma_s / sigma_min
+        } else {
+            Color::default()
+        };
+
+        let mut bbox = AABB::default();
+        boundary.bounding_box(0.0, 1.0, &mut bbox);
+        let [x0, y0, z0]: [Float; 3] = bbox.min().into();
+        let [x1, y1, z1]: [Float; 3] = bbox.max().into();
+        let salt = [x0, y0, z0, x1, y1, z1]
+            .iter()
+            .fold(0u64, |h, f| mix(h ^ f.to_bits()));
+
+        Self {
+            boundary,
+            density,
+            sigma_t,
+            sigma_min,
+            albedo,
+            phase_function: Arc::new(Isotropic::new_with_color(albedo)),
+            salt,
+        }
+    }
+
+    /// Scatters with the Henyey-Greenstein phase function of asymmetry `g`
+    /// instead of isotropically.
+    pub fn with_anisotropy(mut self, g: Float) -> Self {
+        self.phase_function = Arc::new(HenyeyGreenstein::new_with_color(self.albedo, g));
+        self
+    }
+
+    fn rng(&self, ray: &Ray, stream: u64) -> Rng {
+        Rng::new(Rng::for_ray(ray).next_u64() ^ self.salt, stream)
+    }
+
+    /// The intervals of `ray` inside the boundary, clipped to
+    /// [`t_min`, `t_max`], in order.
+    fn segments(&self, ray: &Ray, t_min: Float, t_max: Float) -> Vec<(Float, Float)> {
+        let mut segments = Vec::new();
+        let mut t = -Float::INFINITY;
+        for _ in 0..MAX_SEGMENTS {
+            let mut enter = HitRecord::default();
+            let mut exit = HitRecord::default();
+            if !self.boundary.hit(ray, t, Float::INFINITY, &mut enter)
+                || !self
+                    .boundary
+                    .hit(ray, enter.t + 0.0001, Float::INFINITY, &mut exit)
+            {
+                break;
+            }
+            t = exit.t + 0.0001;
+
+            let start = enter.t.max(t_min);
+            let end = exit.t.min(t_max);
+            if start < end {
+                segments.push((start, end));
+            }
+            if exit.t >= t_max {
+                break;
+            }
+        }
+        segments
+    }
+
+    /// Ratio tracking of the transmittance for the extinction coefficients
+    /// `sigma` along `ray`.
+    fn ratio_tracking(&self, ray: &Ray, t_min: Float, t_max: Float, sigma: Color) -> Color {
+        let mut transmittance = Color::new(1.0, 1.0, 1.0);
+        let majorant = sigma.x().max(sigma.y()).max(sigma.z()) * self.density.max_density();
+        if majorant <= 0.0 {
+            return transmittance;
+        }
+        let ray_length = ray.direction().length();
+        let mut rng = self.rng(ray, 1);
+        for (start, end) in self.segments(ray, t_min, t_max) {
+            let mut t = start;
+            loop {
+                t -= (1.0 - rng.float()).ln() / (majorant * ray_length);
+                if t >= end {
+                    break;
+                }
+                let sigma_t = self.density.density(&ray.at(t)) * sigma;
+                transmittance = transmittance * (Color::new(1.0, 1.0, 1.0) - sigma_t / majorant);
+                if transmittance
+                    .x()
+                    .max(transmittance.y())
+                    .max(transmittance.z())
+                    <= 0.0
+                {
+                    return Color::default();
+                }
+            }
+        }
+        transmittance
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn bounding_box(&self, time0: Float, time1: Float, output_box: &mut AABB) -> bool {
+        self.boundary.bounding_box(time0, time1, output_box)
+    }
+
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        let majorant = self.sigma_min * self.density.max_density();
+        if ray.is_shadow() || majorant <= 0.0 {
+            return false;
+        }
+
+        // delta tracking: tentative collisions with the majorant are real
+        // with probability density / max density
+        let ray_length = ray.direction().length();
+        let mut rng = self.rng(ray, 0);
+        for (start, end) in self.segments(ray, t_min, t_max) {
+            let mut t = start;
+            loop {
+                t -= (1.0 - rng.float()).ln() / (majorant * ray_length);
+                if t >= end {
+                    break;
+                }
+                let p = ray.at(t);
+                if rng.float() * majorant < self.sigma_min * self.density.density(&p) {
+                    rec.t = t;
+                    rec.p = p;
+                    rec.normal = Vec3::new(1.0, 0.0, 0.0);
+                    rec.front_face = true;
+                    rec.material = Some(&*self.phase_function);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn has_media(&self) -> bool {
+        true
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        let sigma = if ray.is_shadow() {
+            self.sigma_t
+        } else {
+            self.sigma_t - Color::new(self.sigma_min, self.sigma_min, self.sigma_min)
+        };
+        self.ratio_tracking(ray, t_min, t_max, sigma)
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Float, Material, Point3, Ray, Vec3, AABB};
+use crate::{Color, Float, HittableList, Material, Point3, Ray, Sampler, Vec3, AABB};
 
 #[derive(Default, Clone)]
 pub struct HitRecord<'a> {
@@ -9,6 +9,12 @@ pub struct HitRecord<'a> {
     pub v: Float,
     pub front_face: bool,
     pub material: Option<&'a dyn Material>,
+    /// The index of refraction on the other side of the surface from the
+    /// material's `Interior`, when the integrator tracks nested media.
+    /// Vacuum otherwise.
+    pub exterior_ior: Option<Float>,
+    /// The hero wavelength of the path in nanometers, in spectral mode.
+    pub wavelength: Option<Float>,
 }
 
 impl HitRecord<'_> {
@@ -41,7 +47,32 @@ pub trait Hittable: Sync + Send {
         0.0
     }
 
-    fn random(&self, _o: &Vec3) -> Vec3 {
+    fn random(&self, _o: &Vec3, _sampler: &mut dyn Sampler) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
+
+    /// Whether this object emits light and implements `pdf_value` and
+    /// `random`, so it can be importance sampled as a light.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Adds the emissive objects nested inside this one to `lights`.
+    /// Containers override this; primitives answer `is_emissive` instead.
+    fn collect_lights(&self, _lights: &mut HittableList) {}
+
+    /// Whether this is or contains a participating medium, which then
+    /// implements `transmittance`.
+    fn has_media(&self) -> bool {
+        false
+    }
+
+    /// The fraction of light, per channel, that the participating media of
+    /// this object let through along `ray` between `t_min` and `t_max`.
+    /// Media stop other rays at collisions sampled in `hit`, which accounts
+    /// for part of it; this is then the rest. Shadow rays pass media, so
+    /// for them it is all of it.
+    fn transmittance(&self, _ray: &Ray, _t_min: Float, _t_max: Float) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
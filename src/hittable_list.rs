@@ -1,8 +1,6 @@
 use std::{ops::Deref, sync::Arc};
 
-use rand::random;
-
-use crate::{Float, HitRecord, Hittable, Point3, Ray, Vec3, AABB};
+use crate::{Color, Float, HitRecord, Hittable, Point3, Ray, Sampler, Vec3, AABB};
 
 type Item = Arc<dyn Hittable>;
 
@@ -30,6 +28,21 @@ impl HittableList {
     pub fn add_shared(&mut self, object: Item) {
         self.objects.push(object);
     }
+
+    /// Collects every emissive object in the list, looking into nested
+    /// containers, for use as the light list of `ray_color`.
+    pub fn lights(&self) -> HittableList {
+        let mut lights = HittableList::new();
+        self.collect_lights(&mut lights);
+        lights
+    }
+
+    /// Adds `object` unless the very same object is already in the list.
+    pub fn add_unique(&mut self, object: Item) {
+        if !self.objects.iter().any(|o| Arc::ptr_eq(o, &object)) {
+            self.objects.push(object);
+        }
+    }
 }
 
 impl Hittable for HittableList {
@@ -83,9 +96,38 @@ impl Hittable for HittableList {
         sum
     }
 
-    fn random(&self, o: &Vec3) -> Vec3 {
-        let idx = random::<usize>() % self.objects.len();
-        self.objects[idx].random(o)
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let n = self.objects.len();
+        let idx = ((sampler.get_1d() * n as Float) as usize).min(n - 1);
+        self.objects[idx].random(o, sampler)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        for object in &self.objects {
+            if object.is_emissive() {
+                lights.add_unique(object.clone());
+            } else {
+                object.collect_lights(lights);
+            }
+        }
+    }
+
+    fn has_media(&self) -> bool {
+        self.objects.iter().any(|object| object.has_media())
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        self.objects
+            .iter()
+            .fold(Color::new(1.0, 1.0, 1.0), |transmittance, object| {
+                transmittance * object.transmittance(ray, t_min, t_max)
+            })
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -0,0 +1,121 @@
+use crate::{Color, Float};
+
+/// Most media a path can be inside of at once; deeper nesting is ignored.
+const MAX_NESTING: usize = 8;
+
+/// Most hidden surfaces a path passes through between two bounces before
+/// it is ended, in case it gets stuck between coincident surfaces.
+pub(crate) const MAX_PASS_THROUGHS: u32 = 64;
+
+/// The medium inside a closed dielectric surface, for nesting dielectrics
+/// such as a liquid in a glass.
+///
+/// Where two such objects overlap, the one with the higher `priority` fills
+/// the overlap and the surface of the other is ignored there, so a liquid
+/// can be modeled slightly overlapping the inner wall of its glass, with a
+/// lower priority.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interior {
+    pub ior: Float,
+    /// Absorption per unit of length, per channel (Beer–Lambert).
+    pub absorption: Color,
+    pub priority: u32,
+}
+
+impl Interior {
+    pub fn new(ior: Float) -> Self {
+        Self {
+            ior,
+            absorption: Color::default(),
+            priority: 0,
+        }
+    }
+
+    /// The absorption that tints light to `color` over `distance`.
+    pub fn absorption_from_tint(color: Color, distance: Float) -> Color {
+        let absorption = |c: Float| -c.clamp(1e-6, 1.0).ln() / distance;
+        Color::new(
+            absorption(color.x()),
+            absorption(color.y()),
+            absorption(color.z()),
+        )
+    }
+
+    /// The fraction of light, per channel, left after `distance`.
+    pub fn transmittance(&self, distance: Float) -> Color {
+        let transmittance = |a: Float| if a > 0.0 { (-a * distance).exp() } else { 1.0 };
+        Color::new(
+            transmittance(self.absorption.x()),
+            transmittance(self.absorption.y()),
+            transmittance(self.absorption.z()),
+        )
+    }
+}
+
+/// The media a path is inside of, with the one of the highest priority
+/// filling the space around it.
+#[derive(Default, Clone, Copy)]
+pub(crate) struct MediumStack {
+    media: [Option<Interior>; MAX_NESTING],
+    len: usize,
+}
+
+impl MediumStack {
+    /// The medium of the highest priority, the most recently entered among
+    /// equals.
+    pub fn current(&self) -> Option<&Interior> {
+        self.media[..self.len]
+            .iter()
+            .flatten()
+            .max_by_key(|medium| medium.priority)
+    }
+
+    /// The medium on the other side of a surface bounding `interior` from
+    /// `interior` itself: the one around the path when entering, the one
+    /// left after leaving otherwise.
+    fn outside(&self, interior: &Interior, entering: bool) -> Option<Interior> {
+        let mut rest = *self;
+        if !entering {
+            rest.remove(interior);
+        }
+        rest.current().copied()
+    }
+
+    /// Whether a surface bounding `interior`, entered if `entering`, is an
+    /// interface between media rather than hidden inside a medium of
+    /// higher priority.
+    pub fn is_interface(&self, interior: &Interior, entering: bool) -> bool {
+        self.outside(interior, entering)
+            .is_none_or(|medium| medium.priority <= interior.priority)
+    }
+
+    /// The index of refraction on the other side of a surface bounding
+    /// `interior`, 1 for vacuum.
+    pub fn exterior_ior(&self, interior: &Interior, entering: bool) -> Float {
+        self.outside(interior, entering)
+            .map_or(1.0, |medium| medium.ior)
+    }
+
+    /// Enters or leaves `interior`.
+    pub fn cross(&mut self, interior: &Interior, entering: bool) {
+        if entering {
+            if self.len < MAX_NESTING {
+                self.media[self.len] = Some(*interior);
+                self.len += 1;
+            }
+        } else {
+            self.remove(interior);
+        }
+    }
+
+    fn remove(&mut self, interior: &Interior) {
+        let position = self.media[..self.len]
+            .iter()
+            .rposition(|medium| medium.as_ref() == Some(interior));
+        if let Some(index) = position {
+            self.media.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+            self.media[self.len] = None;
+        }
+    }
+}
@@ -3,47 +3,87 @@ mod block_box;
 mod bvh_node;
 mod camera;
 mod constant_medium;
+mod density_field;
+mod distribution;
+mod environment;
+mod exr;
 mod flip_face;
+mod grid_medium;
+mod hdr;
+mod heterogeneous_medium;
 mod hittable;
 mod hittable_list;
+mod interior;
 mod material;
+mod matrix;
+mod microfacet;
 mod moving_sphere;
+mod obj;
 mod onb;
 mod pdf;
 mod perlin;
+mod principled;
 mod ray;
 mod rect;
+mod renderer;
+mod rng;
 mod rotate;
+mod sampler;
+mod scene;
+mod spectrum;
 mod sphere;
 mod texture;
+mod tone_map;
+mod transform;
 mod translate;
+mod triangle;
 mod vec3;
+mod volume_file;
 
 pub type Float = f64;
 pub use std::f64::consts::PI;
-use std::ops::Range;
 
 pub use aabb::AABB;
 pub use block_box::BlockBox;
 pub use bvh_node::BvhNode;
-pub use camera::Camera;
+pub use camera::{Camera, CameraConfig};
 pub use constant_medium::ConstantMedium;
+pub use density_field::{DensityField, NoiseDensity, VoxelGrid};
+pub use distribution::{Distribution1D, Distribution2D};
+pub use environment::*;
+pub use exr::{write_exr, ExrPixelType};
 pub use flip_face::FlipFace;
+pub use grid_medium::GridMedium;
+pub use hdr::{read_hdr, write_hdr};
+pub use heterogeneous_medium::HeterogeneousMedium;
 pub use hittable::*;
 pub use hittable_list::HittableList;
+pub use interior::Interior;
 pub use material::*;
+pub use matrix::Matrix4;
+pub use microfacet::*;
 pub use moving_sphere::MovingSphere;
+pub use obj::load_obj;
 pub use onb::Onb;
 pub use pdf::*;
 pub use perlin::Perlin;
-use rand::random;
+pub use principled::Principled;
 pub use ray::Ray;
 pub use rect::*;
+pub use renderer::{render, AdaptiveSettings, RenderOutput, RenderSettings};
+pub use rng::Rng;
 pub use rotate::*;
+pub use sampler::*;
+pub use scene::{Scene, SceneError};
+pub use spectrum::RenderMode;
 pub use sphere::Sphere;
 pub use texture::*;
+pub use tone_map::*;
+pub use transform::Transform;
 pub use translate::Translate;
+pub use triangle::{Mesh, MeshFace, Triangle, TriangleMesh};
 pub use vec3::Vec3;
+pub use volume_file::{load_voxel_grid, read_block_grid, read_nrrd, read_raw_grid};
 
 pub type Point3 = Vec3;
 pub type Color = Vec3;
@@ -62,80 +102,325 @@ pub fn hit_sphere(center: &Point3, radius: Float, ray: &Ray) -> Option<Float> {
     }
 }
 
-pub fn random_range(range: Range<Float>) -> Float {
-    range.start + random::<Float>() * (range.end - range.start)
-}
-
+/// Traces a path from `ray` and returns the radiance it carries back.
+///
+/// Paths end when they escape to the environment, hit a surface that does
+/// not scatter, or after `settings.max_depth` bounces. From bounce
+/// `settings.min_bounces` on they are also ended by Russian roulette: a
+/// path survives with a probability equal to its largest throughput
+/// component and is then weighted up by its inverse, which keeps the
+/// estimate unbiased while spending little time on paths that hardly
+/// contribute.
+///
+/// Light reaches every non-specular bounce in two ways: through a shadow
+/// ray towards a point sampled on `lights` or a direction sampled from the
+/// environment (next event estimation), and by the scattered ray hitting
+/// an emitter or escaping. Both estimate the same light, so each is
+/// weighted by `settings.mis` against the density the other strategy has
+/// for the same direction. Participating media attenuate the light that
+/// passes them on both kinds of rays by their transmittance.
+///
+/// The scattered ray is sampled from the material and, if there are any,
+/// from `targets` with equal probability, which finds light that reaches
+/// a surface through objects shadow rays cannot pass, such as the caustic
+/// below a glass sphere.
+///
+/// Paths keep track of the dielectric interiors they are inside of, which
+/// gives refraction the index on the far side of a surface and absorbs
+/// light along the way. Surfaces hidden inside an interior of higher
+/// priority are passed through without counting as a bounce, up to
+/// `interior::MAX_PASS_THROUGHS` of them in a row.
+///
+/// In `RenderMode::Spectral` the components of the colors along the path
+/// are the light at three sampled wavelengths instead of RGB: the colors
+/// of the scene are upsampled to those wavelengths as they are met, and
+/// the result is converted back to RGB. After a dispersive bounce only the
+/// hero wavelength goes on.
+///
+/// Every segment of the path is seeded from `sampler`, so that the free
+/// paths media draw along it follow the seed and the pixel sample.
 pub fn ray_color<'a>(
     ray: &Ray,
-    background: &Color,
+    environment: &'a dyn Environment,
     world: &'a impl Hittable,
-    lights: &'a dyn Hittable,
-    depth: i32,
+    lights: &'a HittableList,
+    targets: &'a HittableList,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
 ) -> Color {
-    if depth <= 0 {
-        return Color::default();
+    let light_sampling = LightSampling::new(lights, environment);
+    let has_media = world.has_media();
+    let mut media = interior::MediumStack::default();
+    let mut wavelengths = match settings.mode {
+        RenderMode::Rgb => None,
+        RenderMode::Spectral => Some(spectrum::SampledWavelengths::sample(sampler.get_1d())),
+    };
+    let mut radiance = Color::default();
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut ray = ray.with_seed(ray_seed(sampler));
+    // the origin of `ray` and the density it was sampled with, unless it
+    // was a specular bounce or the camera ray
+    let mut scattered_from: Option<(Point3, Float)> = None;
+    let emission_weight =
+        |scattered_from: Option<(Point3, Float)>, direction: &Vec3| match scattered_from {
+            Some((origin, material_pdf)) if !light_sampling.is_empty() => {
+                let light_pdf = light_sampling.with_pdf(origin, |pdf| pdf.value(direction));
+                settings.mis.weight(material_pdf, light_pdf)
+            }
+            _ => 1.0,
+        };
+
+    let mut bounces = 0;
+    let mut pass_throughs = 0;
+    while bounces < settings.max_depth {
+        let mut rec = HitRecord::default();
+        let hit = world.hit(&ray, 0.001, f64::INFINITY, &mut rec);
+        if has_media {
+            let t_max = if hit { rec.t } else { f64::INFINITY };
+            throughput =
+                throughput * in_mode(&wavelengths, world.transmittance(&ray, 0.001, t_max));
+        }
+        if let Some(medium) = media.current() {
+            let t = if hit { rec.t } else { f64::INFINITY };
+            throughput = throughput
+                * in_mode(
+                    &wavelengths,
+                    medium.transmittance(t * ray.direction().length()),
+                );
+        }
+        if !hit {
+            let direction = ray.direction().unit_vector();
+            radiance += emission_weight(scattered_from, &direction)
+                * throughput
+                * in_mode(&wavelengths, environment.radiance(&direction));
+            break;
+        }
+        let interior = rec
+            .material
+            .and_then(|material| material.interior())
+            .copied();
+        if let Some(interior) = &interior {
+            if !media.is_interface(interior, rec.front_face) {
+                // hidden inside a medium of higher priority, pass through
+                media.cross(interior, rec.front_face);
+                ray = Ray::new(rec.p, ray.direction(), ray.time()).with_seed(ray.seed());
+                pass_throughs += 1;
+                if pass_throughs > interior::MAX_PASS_THROUGHS {
+                    break;
+                }
+                continue;
+            }
+            rec.exterior_ior = Some(media.exterior_ior(interior, rec.front_face));
+        }
+        rec.wavelength = wavelengths.map(|w| w.hero());
+        sampler.start_bounce();
+        let mut srec = ScatterRecord::default();
+        let Some(material) = rec.material else {
+            break;
+        };
+        if material.is_emissive() {
+            radiance += emission_weight(scattered_from, &ray.direction())
+                * throughput
+                * in_mode(
+                    &wavelengths,
+                    material.emitted(&ray, &rec, rec.u, rec.v, &rec.p),
+                );
+        }
+
+        if !material.scatter(&ray, &rec, &mut srec, sampler) {
+            break;
+        }
+        if srec.dispersive {
+            if let Some(wavelengths) = &mut wavelengths {
+                wavelengths.terminate_secondary();
+            }
+        }
+        if let Some(specular_ray) = srec.specular_ray {
+            throughput = throughput * in_mode(&wavelengths, srec.attenuation);
+            ray = specular_ray;
+            scattered_from = None;
+        } else {
+            let material_pdf = srec.pdf_ptr.as_deref().unwrap();
+            let target_pdf = HittablePdf::new(targets, rec.p);
+            let continuation_pdf = MixturePdf::new(vec![
+                (material_pdf, 1.0),
+                (&target_pdf, if targets.is_empty() { 0.0 } else { 1.0 }),
+            ]);
+            if !light_sampling.is_empty() {
+                radiance += throughput
+                    * sample_light(
+                        world,
+                        &light_sampling,
+                        &ray,
+                        &rec,
+                        &srec,
+                        &continuation_pdf,
+                        &media,
+                        &wavelengths,
+                        settings.mis,
+                        sampler,
+                    );
+            }
+
+            let scattered = Ray::new(rec.p, continuation_pdf.generate(sampler), ray.time());
+            let pdf_val = continuation_pdf.value(&scattered.direction());
+            if pdf_val <= 0.0 {
+                break;
+            }
+
+            throughput = throughput
+                * in_mode(&wavelengths, material.bsdf(&ray, &rec, &srec, &scattered))
+                / (srec.lobe_probability * pdf_val);
+            ray = scattered;
+            scattered_from = Some((rec.p, pdf_val));
+        }
+        ray = ray.with_seed(ray_seed(sampler));
+        if let Some(interior) = &interior {
+            if ray.direction().dot(&rec.normal) < 0.0 {
+                media.cross(interior, rec.front_face);
+            }
+        }
+
+        bounces += 1;
+        pass_throughs = 0;
+        if bounces >= settings.min_bounces {
+            let survival = throughput
+                .x()
+                .max(throughput.y())
+                .max(throughput.z())
+                .min(1.0);
+            if sampler.get_1d() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
     }
-    let mut rec = HitRecord::default();
-    if !world.hit(ray, 0.001, f64::INFINITY, &mut rec) {
-        return background.clone();
+
+    match wavelengths {
+        Some(wavelengths) => wavelengths.to_rgb(radiance),
+        None => radiance,
     }
-    let mut srec = ScatterRecord::default();
-    let emitted = rec.material.map_or(Color::default(), |m| {
-        m.emitted(ray, &rec, rec.u, rec.v, &rec.p)
-    });
+}
 
-    if !rec
-        .material
-        .map_or(false, |mat| mat.scatter(ray, &rec, &mut srec))
-    {
-        emitted
-    } else if let Some(specular_ray) = srec.specular_ray {
-        srec.attenuation * ray_color(&specular_ray, background, world, lights, depth - 1)
-    } else {
-        let light = HittablePdf::new(lights, rec.p);
-        let mixed_pdf = MixturePdf::new(&light, srec.pdf_ptr.as_deref().unwrap());
+/// A seed for the next segment of a path.
+fn ray_seed(sampler: &mut dyn Sampler) -> u64 {
+    (sampler.get_1d() * (1u64 << 53) as Float) as u64
+}
+
+/// `color` as a path carries it: at the `wavelengths` of the path in
+/// spectral mode, unchanged otherwise.
+fn in_mode(wavelengths: &Option<spectrum::SampledWavelengths>, color: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.upsample(color),
+        None => color,
+    }
+}
 
-        let scattered = Ray::new(rec.p, mixed_pdf.generate(), ray.time());
-        let pdf_val = mixed_pdf.value(&scattered.direction());
+/// Where next event estimation looks for light: the emissive objects and
+/// the environment, with equal probability when both emit.
+struct LightSampling<'a> {
+    lights: &'a HittableList,
+    environment: &'a dyn Environment,
+    weights: [Float; 2],
+}
+
+impl<'a> LightSampling<'a> {
+    fn new(lights: &'a HittableList, environment: &'a dyn Environment) -> Self {
+        let weight = |sampled: bool| if sampled { 1.0 } else { 0.0 };
+        Self {
+            lights,
+            environment,
+            weights: [
+                weight(!lights.is_empty()),
+                weight(environment.is_emissive()),
+            ],
+        }
+    }
 
-        emitted
-            + srec.attenuation
-                * rec.material.unwrap().scattering_pdf(ray, &rec, &scattered)
-                * ray_color(&scattered, background, world, lights, depth - 1)
-                / pdf_val
+    fn is_empty(&self) -> bool {
+        self.weights.iter().all(|&w| w == 0.0)
+    }
+
+    /// Calls `f` with the density of light directions seen from `origin`.
+    fn with_pdf<R>(&self, origin: Point3, f: impl FnOnce(&dyn Pdf) -> R) -> R {
+        let objects = HittablePdf::new(self.lights, origin);
+        let environment = EnvironmentPdf::new(self.environment);
+        f(&MixturePdf::new(vec![
+            (&objects, self.weights[0]),
+            (&environment, self.weights[1]),
+        ]))
     }
 }
 
-pub fn render<'a>(
-    world: &'a impl Hittable,
-    lights: &'a dyn Hittable,
-    background: Color,
-    cam: Camera,
-    image_width: u32,
-    image_height: u32,
-    samples_per_pixel: u32,
-    max_depth: i32,
-) -> Vec<Color> {
-    println!("begin render");
-    let mut image: Vec<Color> = Vec::with_capacity((image_width * image_height) as usize);
-
-    for j in (0..image_height).into_iter().rev() {
-        let v = j as Float / (image_height - 1) as Float;
-        for i in 0..image_width {
-            let u = i as Float / (image_width - 1) as Float;
-            let mut color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..samples_per_pixel {
-                let ray = cam.get_ray(
-                    u + random::<Float>() / (image_width - 1) as Float,
-                    v + random::<Float>() / (image_height - 1) as Float,
-                );
-                color += ray_color(&ray, &background, world, lights, max_depth);
-            }
-            image.push(color);
+/// Next event estimation: the light scattered at `rec` towards the origin
+/// of `ray_in` from a direction sampled towards the lights, if nothing
+/// blocks the shadow ray and attenuated by the media it crosses, including
+/// the interior it travels in, weighted against sampling the continuation
+/// of the path with `continuation_pdf`.
+#[allow(clippy::too_many_arguments)]
+fn sample_light(
+    world: &impl Hittable,
+    light_sampling: &LightSampling,
+    ray_in: &Ray,
+    rec: &HitRecord,
+    srec: &ScatterRecord,
+    continuation_pdf: &dyn Pdf,
+    media: &interior::MediumStack,
+    wavelengths: &Option<spectrum::SampledWavelengths>,
+    heuristic: MisHeuristic,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    let (direction, pdf_val) = light_sampling.with_pdf(rec.p, |pdf| {
+        let direction = pdf.generate(sampler);
+        (direction, pdf.value(&direction))
+    });
+    if pdf_val <= 0.0 {
+        return Color::default();
+    }
+    let shadow_ray = Ray::new_shadow(rec.p, direction, ray_in.time()).with_seed(ray_in.seed());
+    let material = rec.material.unwrap();
+    let bsdf = material.bsdf(ray_in, rec, srec, &shadow_ray) / srec.lobe_probability;
+    if bsdf.x() <= 0.0 && bsdf.y() <= 0.0 && bsdf.z() <= 0.0 {
+        return Color::default();
+    }
+
+    let mut light_rec = HitRecord::default();
+    let hit = world.hit(&shadow_ray, 0.001, f64::INFINITY, &mut light_rec);
+    let t_max = if hit { light_rec.t } else { f64::INFINITY };
+    let mut transmittance = if world.has_media() {
+        world.transmittance(&shadow_ray, 0.001, t_max)
+    } else {
+        Color::new(1.0, 1.0, 1.0)
+    };
+    let mut media = *media;
+    if let Some(interior) = material.interior() {
+        if direction.dot(&rec.normal) < 0.0 {
+            media.cross(interior, rec.front_face);
         }
     }
+    if let Some(medium) = media.current() {
+        transmittance = transmittance * medium.transmittance(t_max * direction.length());
+    }
+    let emitted = if hit {
+        match light_rec.material {
+            Some(light_material) => light_material.emitted(
+                &shadow_ray,
+                &light_rec,
+                light_rec.u,
+                light_rec.v,
+                &light_rec.p,
+            ),
+            None => return Color::default(),
+        }
+    } else {
+        light_sampling
+            .environment
+            .radiance(&direction.unit_vector())
+    };
 
-    println!("finish render");
-    return image;
+    heuristic.weight(pdf_val, continuation_pdf.value(&direction))
+        * in_mode(wavelengths, bsdf)
+        * in_mode(wavelengths, transmittance)
+        * in_mode(wavelengths, emitted)
+        / pdf_val
 }
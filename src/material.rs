@@ -1,17 +1,54 @@
 use std::sync::Arc;
 
-use crate::{Color, CosinePdf, Float, HitRecord, Pdf, Point3, Ray, SolidColor, Texture, Vec3, PI};
-use rand::random;
+use crate::{
+    fresnel_conductor, fresnel_dielectric, henyey_greenstein, Color, CosinePdf, Float,
+    HenyeyGreensteinPdf, HitRecord, Interior, MicrofacetPdf, Pdf, Point3, Ray, Sampler, SolidColor,
+    Texture, TrowbridgeReitz, Vec3, PI,
+};
 
-#[derive(Default)]
 pub struct ScatterRecord {
     pub specular_ray: Option<Ray>,
     pub attenuation: Color,
     pub pdf_ptr: Option<Box<dyn Pdf>>,
+    /// The probability with which `scatter` picked the lobes `pdf_ptr`
+    /// samples over others it cannot sample by density, such as a smooth
+    /// coat over a diffuse base. `bsdf` covers every lobe, so it is divided
+    /// by this wherever it is used with `pdf_ptr`.
+    pub lobe_probability: Float,
+    /// The materials each `MixMaterial` that scattered picked, by its
+    /// address, for `bsdf` to evaluate the same ones.
+    pub mix_choices: Vec<(usize, bool)>,
+    /// The `lobe_probability` of the base of each `CoatedMaterial` that
+    /// scattered, by its address, or zero where the base scattered
+    /// specularly and `bsdf` leaves it out.
+    pub base_lobe_probabilities: Vec<(usize, Float)>,
+    /// Whether the scattered direction depends on `rec.wavelength`, so that
+    /// the path only carries light of that wavelength on.
+    pub dispersive: bool,
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self {
+            specular_ray: None,
+            attenuation: Color::default(),
+            pdf_ptr: None,
+            lobe_probability: 1.0,
+            mix_choices: Vec::new(),
+            base_lobe_probabilities: Vec::new(),
+            dispersive: false,
+        }
+    }
 }
 
 pub trait Material: Sync + Send {
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
         false
     }
 
@@ -19,9 +56,30 @@ pub trait Material: Sync + Send {
         0.0
     }
 
+    /// The BSDF times the cosine of `scattered` to the normal: how much of
+    /// the light arriving along `scattered` leaves towards the origin of
+    /// `ray_in`, for a non-specular `srec` from `scatter`. The default,
+    /// `srec.attenuation` times `scattering_pdf`, suits materials that
+    /// scatter every color alike.
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, srec: &ScatterRecord, scattered: &Ray) -> Color {
+        srec.attenuation * self.scattering_pdf(ray_in, rec, scattered)
+    }
+
     fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord, _u: Float, _v: Float, _p: &Point3) -> Color {
         Color::default()
     }
+
+    /// Whether `emitted` can be non-zero, so that surfaces using this
+    /// material are worth sampling as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// The medium inside closed surfaces of this material, for dielectrics
+    /// that nest or absorb.
+    fn interior(&self) -> Option<&Interior> {
+        None
+    }
 }
 
 pub struct EmptyMaterial;
@@ -51,7 +109,13 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord, scatter_record: &mut ScatterRecord) -> bool {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        rec: &HitRecord,
+        scatter_record: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
         scatter_record.specular_ray = None;
         scatter_record.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
         scatter_record.pdf_ptr = Some(Box::new(CosinePdf::new(&rec.normal)));
@@ -84,9 +148,15 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
         let reflected = ray_in.direction().unit_vector().reflect(&rec.normal);
-        let direction = reflected + self.fuzz * Vec3::random_in_unit_sphere();
+        let direction = reflected + self.fuzz * Vec3::random_in_unit_sphere(sampler);
         srec.specular_ray = Some(Ray::new(rec.p, direction, ray_in.time()));
         srec.attenuation = self.albedo;
         srec.pdf_ptr = None;
@@ -94,18 +164,93 @@ impl Material for Metal {
     }
 }
 
+/// An index of refraction that varies with the wavelength, for dispersion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, with `λ` in micrometers.
+    Cauchy { a: Float, b: Float },
+    /// `n² = 1 + Σ b λ² / (λ² - c)`, with `λ` in micrometers.
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    /// Borosilicate crown glass, N-BK7.
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    pub const DIAMOND: Self = Self::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030625, 0.011236, 0.0],
+    };
+
+    /// The index of refraction at `wavelength`, in nanometers.
+    pub fn ior(&self, wavelength: Float) -> Float {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * l2 / (l2 - c))
+                    .sum::<Float>())
+            .sqrt(),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Dielectric {
     ir: Float,
+    dispersion: Option<Dispersion>,
+    interior: Option<Interior>,
+}
+
+/// The index of the far side of a dielectric surface with index `ir` over
+/// that of the side the ray comes from.
+pub(crate) fn relative_ior(ir: Float, rec: &HitRecord) -> Float {
+    let exterior = rec.exterior_ior.unwrap_or(1.0);
+    if rec.front_face {
+        ir / exterior
+    } else {
+        exterior / ir
+    }
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: Float) -> Self {
         Self {
             ir: index_of_refraction,
+            dispersion: None,
+            interior: None,
         }
     }
 
+    /// A dielectric that refracts every wavelength by its own index in
+    /// spectral mode, and by the index at the sodium D line (589.3 nm)
+    /// otherwise.
+    pub fn new_dispersive(dispersion: Dispersion) -> Self {
+        Self {
+            dispersion: Some(dispersion),
+            ..Self::new(dispersion.ior(589.3))
+        }
+    }
+
+    /// Absorbs light travelling inside, per unit of length and channel.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.interior
+            .get_or_insert(Interior::new(self.ir))
+            .absorption = absorption;
+        self
+    }
+
+    /// Nests the dielectric among others by `priority`, see `Interior`.
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.interior.get_or_insert(Interior::new(self.ir)).priority = priority;
+        self
+    }
+
     fn reflectance(cosine: Float, ref_idx: Float) -> Float {
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
         let r0 = r0 * r0;
@@ -114,61 +259,655 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
         srec.attenuation = Color::new(1.0, 1.0, 1.0);
         srec.pdf_ptr = None;
-        let refraction_ratio = if rec.front_face {
-            1.0 / self.ir
-        } else {
-            self.ir
+        let ir = match (self.dispersion, rec.wavelength) {
+            (Some(dispersion), Some(wavelength)) => {
+                srec.dispersive = true;
+                dispersion.ior(wavelength)
+            }
+            _ => self.ir,
         };
+        let refraction_ratio = 1.0 / relative_ior(ir, rec);
         let unit_direction = ray_in.direction().unit_vector();
         let cos_theta = rec.normal.dot(&-unit_direction).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > random() {
-                unit_direction.reflect(&rec.normal)
-            } else {
-                unit_direction.refract(&rec.normal, refraction_ratio)
-            };
+        let direction = if cannot_refract
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            unit_direction.reflect(&rec.normal)
+        } else {
+            unit_direction.refract(&rec.normal, refraction_ratio)
+        };
+        srec.specular_ray = Some(Ray::new(rec.p, direction, ray_in.time()));
+        true
+    }
+
+    fn interior(&self) -> Option<&Interior> {
+        self.interior.as_ref()
+    }
+}
+
+/// A metal with a GGX microfacet surface and the Fresnel reflectance of
+/// its complex index of refraction `eta + i k`, given per color channel.
+#[derive(Clone, Copy)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: Float) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    pub fn gold(roughness: Float) -> Self {
+        Self::new(
+            Color::new(0.143, 0.374, 1.442),
+            Color::new(3.983, 2.385, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: Float) -> Self {
+        Self::new(
+            Color::new(0.200, 0.924, 1.102),
+            Color::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: Float) -> Self {
+        Self::new(
+            Color::new(1.657, 0.880, 0.521),
+            Color::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord) -> MicrofacetPdf {
+        MicrofacetPdf::new(&rec.normal, &-ray_in.direction(), self.distribution, None)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        let cos = rec.normal.dot(&-ray_in.direction().unit_vector());
+        srec.attenuation = fresnel_conductor(cos, &self.eta, &self.k);
+        if self.distribution.is_smooth() {
+            let reflected = ray_in.direction().unit_vector().reflect(&rec.normal);
+            srec.specular_ray = Some(Ray::new(rec.p, reflected, ray_in.time()));
+            srec.pdf_ptr = None;
+        } else {
+            srec.specular_ray = None;
+            srec.pdf_ptr = Some(Box::new(self.pdf(ray_in, rec)));
+        }
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.pdf(ray_in, rec).value(&scattered.direction())
+    }
+
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, _srec: &ScatterRecord, scattered: &Ray) -> Color {
+        self.pdf(ray_in, rec)
+            .scattering(&scattered.direction(), |cos| {
+                fresnel_conductor(cos, &self.eta, &self.k)
+            })
+    }
+}
+
+/// Glass with a GGX microfacet surface, which blurs both reflection and
+/// refraction.
+#[derive(Clone, Copy)]
+pub struct RoughDielectric {
+    ir: Float,
+    distribution: TrowbridgeReitz,
+    interior: Option<Interior>,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: Float, roughness: Float) -> Self {
+        Self {
+            ir: index_of_refraction,
+            distribution: TrowbridgeReitz::new(roughness),
+            interior: None,
+        }
+    }
+
+    /// Absorbs light travelling inside, per unit of length and channel.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.interior
+            .get_or_insert(Interior::new(self.ir))
+            .absorption = absorption;
+        self
+    }
+
+    /// Nests the dielectric among others by `priority`, see `Interior`.
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.interior.get_or_insert(Interior::new(self.ir)).priority = priority;
+        self
+    }
+
+    fn relative_ir(&self, rec: &HitRecord) -> Float {
+        relative_ior(self.ir, rec)
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord) -> MicrofacetPdf {
+        MicrofacetPdf::new(
+            &rec.normal,
+            &-ray_in.direction(),
+            self.distribution,
+            Some(self.relative_ir(rec)),
+        )
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.attenuation = Color::new(1.0, 1.0, 1.0);
+        if !self.distribution.is_smooth() {
+            srec.specular_ray = None;
+            srec.pdf_ptr = Some(Box::new(self.pdf(ray_in, rec)));
+            return true;
+        }
+
+        let eta = self.relative_ir(rec);
+        let unit_direction = ray_in.direction().unit_vector();
+        let cos_theta = rec.normal.dot(&-unit_direction).min(1.0);
+        let direction = if sampler.get_1d() < fresnel_dielectric(cos_theta, eta) {
+            unit_direction.reflect(&rec.normal)
+        } else {
+            unit_direction.refract(&rec.normal, 1.0 / eta)
+        };
         srec.specular_ray = Some(Ray::new(rec.p, direction, ray_in.time()));
+        srec.pdf_ptr = None;
         true
     }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.pdf(ray_in, rec).value(&scattered.direction())
+    }
+
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, _srec: &ScatterRecord, scattered: &Ray) -> Color {
+        let eta = self.relative_ir(rec);
+        self.pdf(ray_in, rec)
+            .scattering(&scattered.direction(), |cos| {
+                let reflectance = fresnel_dielectric(cos, eta);
+                Color::new(reflectance, reflectance, reflectance)
+            })
+    }
+
+    fn interior(&self) -> Option<&Interior> {
+        self.interior.as_ref()
+    }
+}
+
+/// Picks `first` or `second` per hit, `second` with the probability read
+/// from the first channel of `weight`. `scatter` draws the choice and
+/// records it in the `ScatterRecord` for `bsdf`; `scattering_pdf` and
+/// `emitted` have no record to read, so they blend both.
+pub struct MixMaterial {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    weight: Arc<dyn Texture>,
 }
 
+impl MixMaterial {
+    pub fn new(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        weight: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            weight,
+        }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> Float {
+        self.weight.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0)
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn material(&self, second: bool) -> &dyn Material {
+        if second {
+            self.second.as_ref()
+        } else {
+            self.first.as_ref()
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let second = sampler.get_1d() < self.weight(rec);
+        srec.mix_choices.push((self.key(), second));
+        self.material(second).scatter(ray_in, rec, srec, sampler)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.scattering_pdf(ray_in, rec, scattered)
+            + weight * self.second.scattering_pdf(ray_in, rec, scattered)
+    }
+
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, srec: &ScatterRecord, scattered: &Ray) -> Color {
+        // the chosen material alone, as it was picked with its own weight
+        let second = srec.mix_choices.contains(&(self.key(), true));
+        self.material(second).bsdf(ray_in, rec, srec, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Point3) -> Color {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.emitted(ray_in, rec, u, v, p)
+            + weight * self.second.emitted(ray_in, rec, u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.first.is_emissive() || self.second.is_emissive()
+    }
+
+    /// The interior of `first`, or else of `second`: without a hit to read
+    /// the choice from, a mix of two media takes the first.
+    fn interior(&self) -> Option<&Interior> {
+        self.first.interior().or_else(|| self.second.interior())
+    }
+}
+
+/// A clear dielectric layer over a `base` material, such as varnish over
+/// wood. Light reflects off the layer with its Fresnel reflectance and
+/// reaches the base with the rest, crossing the layer again on the way
+/// out. The layer is smooth or, with a positive `roughness`, glossy.
+pub struct CoatedMaterial {
+    base: Arc<dyn Material>,
+    ir: Float,
+    distribution: TrowbridgeReitz,
+}
+
+impl CoatedMaterial {
+    pub fn new(base: Arc<dyn Material>, index_of_refraction: Float, roughness: Float) -> Self {
+        Self {
+            base,
+            ir: index_of_refraction,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    fn reflectance(&self, cos: Float) -> Float {
+        fresnel_dielectric(cos.abs(), self.ir)
+    }
+
+    fn key(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn coat_pdf(&self, ray_in: &Ray, rec: &HitRecord) -> MicrofacetPdf {
+        MicrofacetPdf::new(&rec.normal, &-ray_in.direction(), self.distribution, None)
+    }
+
+    /// The reflectance of the layer towards the viewer, which is also the
+    /// probability of sampling the layer rather than the base.
+    fn coat_probability(&self, ray_in: &Ray, rec: &HitRecord) -> Float {
+        self.reflectance(rec.normal.dot(&-ray_in.direction().unit_vector()))
+    }
+}
+
+/// The density of the directions a `CoatedMaterial` samples: a mixture of
+/// the glossy layer, if any, and the base, if it did not scatter
+/// specularly.
+struct CoatedPdf {
+    coat: Option<MicrofacetPdf>,
+    base: Option<Box<dyn Pdf>>,
+    coat_probability: Float,
+}
+
+impl Pdf for CoatedPdf {
+    fn value(&self, direction: &Vec3) -> Float {
+        // the glossy layer rejects samples with the zero vector
+        if direction.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let coat = self.coat.as_ref().map_or(0.0, |p| p.value(direction));
+        let base = self.base.as_ref().map_or(0.0, |p| p.value(direction));
+        self.coat_probability * coat + (1.0 - self.coat_probability) * base
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        match (&self.coat, &self.base) {
+            (Some(coat), Some(base)) => {
+                if sampler.get_1d() < self.coat_probability {
+                    coat.generate(sampler)
+                } else {
+                    base.generate(sampler)
+                }
+            }
+            (Some(coat), None) => coat.generate(sampler),
+            (None, Some(base)) => base.generate(sampler),
+            (None, None) => Vec3::default(),
+        }
+    }
+}
+
+impl Material for CoatedMaterial {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let coat_probability = self.coat_probability(ray_in, rec);
+        let mut base_srec = ScatterRecord::default();
+        let base_scatters = self.base.scatter(ray_in, rec, &mut base_srec, sampler);
+        let smooth = self.distribution.is_smooth();
+        srec.mix_choices.append(&mut base_srec.mix_choices);
+        srec.base_lobe_probabilities
+            .append(&mut base_srec.base_lobe_probabilities);
+        // the base samples by density with this probability, and only then
+        // does `bsdf` include it
+        let base_lobe_probability = match base_srec.pdf_ptr {
+            Some(_) if base_scatters => base_srec.lobe_probability,
+            _ => 0.0,
+        };
+        srec.base_lobe_probabilities
+            .push((self.key(), base_lobe_probability));
+
+        // both lobes have a density, the pdf chooses between them; the base
+        // may have picked its lobes among specular ones, which `bsdf`
+        // accounts for
+        if base_lobe_probability > 0.0 && !smooth {
+            srec.specular_ray = None;
+            srec.attenuation = base_srec.attenuation;
+            srec.pdf_ptr = Some(Box::new(CoatedPdf {
+                coat: Some(self.coat_pdf(ray_in, rec)),
+                base: base_srec.pdf_ptr,
+                coat_probability,
+            }));
+            return true;
+        }
+
+        // otherwise one of them is specular, so the lobe is chosen here
+        let choose_coat = sampler.get_1d() < coat_probability;
+        match base_srec.pdf_ptr {
+            _ if choose_coat && smooth => {
+                let reflected = ray_in.direction().unit_vector().reflect(&rec.normal);
+                srec.specular_ray = Some(Ray::new(rec.p, reflected, ray_in.time()));
+                srec.attenuation = Color::new(1.0, 1.0, 1.0);
+                srec.pdf_ptr = None;
+            }
+            _ if choose_coat => {
+                srec.specular_ray = None;
+                srec.attenuation = Color::new(1.0, 1.0, 1.0);
+                srec.pdf_ptr = Some(Box::new(CoatedPdf {
+                    coat: Some(self.coat_pdf(ray_in, rec)),
+                    base: None,
+                    coat_probability: 1.0,
+                }));
+                srec.lobe_probability = coat_probability;
+            }
+            _ if !base_scatters => return false,
+            Some(base_pdf) => {
+                srec.specular_ray = None;
+                srec.attenuation = base_srec.attenuation;
+                srec.pdf_ptr = Some(Box::new(CoatedPdf {
+                    coat: None,
+                    base: Some(base_pdf),
+                    coat_probability: 0.0,
+                }));
+                srec.lobe_probability = 1.0 - coat_probability;
+            }
+            None => {
+                // a specular base, seen through the layer twice; choosing
+                // the base already accounts for the way in
+                let specular_ray = base_srec.specular_ray.unwrap();
+                let cos = rec.normal.dot(&specular_ray.direction().unit_vector());
+                srec.specular_ray = Some(specular_ray);
+                srec.attenuation = base_srec.attenuation * (1.0 - self.reflectance(cos));
+                srec.pdf_ptr = None;
+                srec.dispersive = base_srec.dispersive;
+            }
+        }
+        true
+    }
+
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, srec: &ScatterRecord, scattered: &Ray) -> Color {
+        let coat = if self.distribution.is_smooth() {
+            Color::default()
+        } else {
+            self.coat_pdf(ray_in, rec)
+                .scattering(&scattered.direction(), |cos| {
+                    let reflectance = self.reflectance(cos);
+                    Color::new(reflectance, reflectance, reflectance)
+                })
+        };
+        // the base as `scatter` left it: out if it scattered specularly,
+        // else weighted by how likely it was to pick the lobes it samples
+        let base_lobe_probability = srec
+            .base_lobe_probabilities
+            .iter()
+            .find(|&&(key, _)| key == self.key())
+            .map_or(1.0, |&(_, probability)| probability);
+        if base_lobe_probability <= 0.0 {
+            return coat;
+        }
+        let cos_i = rec.normal.dot(&scattered.direction().unit_vector());
+        let transmittance =
+            (1.0 - self.coat_probability(ray_in, rec)) * (1.0 - self.reflectance(cos_i));
+        coat + transmittance * self.base.bsdf(ray_in, rec, srec, scattered) / base_lobe_probability
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        let coat_probability = self.coat_probability(ray_in, rec);
+        let coat = if self.distribution.is_smooth() {
+            0.0
+        } else {
+            self.coat_pdf(ray_in, rec).value(&scattered.direction())
+        };
+        coat_probability * coat
+            + (1.0 - coat_probability) * self.base.scattering_pdf(ray_in, rec, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Point3) -> Color {
+        (1.0 - self.coat_probability(ray_in, rec)) * self.base.emitted(ray_in, rec, u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn interior(&self) -> Option<&Interior> {
+        self.base.interior()
+    }
+}
+
+/// How the radiance of a `DiffuseLight` varies with the angle to its
+/// normal.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Falloff {
+    /// The same radiance in every direction, a Lambertian emitter.
+    Uniform,
+    /// Full radiance up to `inner` degrees from the normal, fading smoothly
+    /// to nothing at `outer` degrees.
+    Spot { inner: Float, outer: Float },
+    /// Radiance scaled by a profile given at evenly spaced angles from 0 to
+    /// 90 degrees and interpolated linearly, like a photometric (IES) file.
+    Profile(Vec<Float>),
+}
+
+impl Falloff {
+    /// The factor for light leaving at `cos` to the normal.
+    pub fn scale(&self, cos: Float) -> Float {
+        match self {
+            Self::Uniform => 1.0,
+            Self::Spot { inner, outer } => {
+                let cos_inner = inner.to_radians().cos();
+                let cos_outer = outer.to_radians().cos();
+                if cos >= cos_inner {
+                    1.0
+                } else if cos <= cos_outer {
+                    0.0
+                } else {
+                    let t = (cos - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                }
+            }
+            Self::Profile(values) => {
+                if values.len() < 2 {
+                    return values.first().copied().unwrap_or(1.0);
+                }
+                let x = cos.clamp(0.0, 1.0).acos() / (PI / 2.0) * (values.len() - 1) as Float;
+                let i = (x as usize).min(values.len() - 2);
+                let t = x - i as Float;
+                (1.0 - t) * values[i] + t * values[i + 1]
+            }
+        }
+    }
+
+    /// The integral of `scale` times the cosine over the hemisphere, π for
+    /// a uniform emitter: the power leaving unit area at unit radiance.
+    fn projected_solid_angle(&self) -> Float {
+        if let Self::Uniform = self {
+            return PI;
+        }
+        const STEPS: usize = 1024;
+        let sum: Float = (0..STEPS)
+            .map(|i| {
+                let cos = (i as Float + 0.5) / STEPS as Float;
+                self.scale(cos) * cos
+            })
+            .sum();
+        2.0 * PI * sum / STEPS as Float
+    }
+}
+
+/// An emitter of the radiance given by its texture, from the front face
+/// only unless it is two-sided.
+///
+/// Light sampling stays with the shapes, which sample their area or solid
+/// angle regardless of these options: directions that receive nothing
+/// still have the density the shape gives them, so estimates stay
+/// unbiased and MIS weights consistent.
+#[derive(Clone)]
 pub struct DiffuseLight {
     emit: Arc<dyn Texture>,
+    two_sided: bool,
+    falloff: Falloff,
+    /// Power leaving unit area at unit radiance, cached for `falloff`.
+    projected_solid_angle: Float,
+    /// With the texture read as power, the area it spreads over.
+    power_area: Option<Float>,
 }
 
 impl DiffuseLight {
     pub fn new_with_color(color: Color) -> Self {
+        Self::new_with_texture(Arc::new(SolidColor::from(color)))
+    }
+
+    pub fn new_with_texture(texture: Arc<dyn Texture>) -> Self {
         Self {
-            emit: Arc::new(SolidColor::from(color)),
+            emit: texture,
+            two_sided: false,
+            falloff: Falloff::Uniform,
+            projected_solid_angle: PI,
+            power_area: None,
         }
     }
 
-    pub fn new_with_texture(texture: Arc<dyn Texture>) -> Self {
-        Self { emit: texture }
+    /// Emits from the back face as well.
+    pub fn two_sided(mut self) -> Self {
+        self.two_sided = true;
+        self
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.projected_solid_angle = falloff.projected_solid_angle();
+        self.falloff = falloff;
+        self
+    }
+
+    /// Reads the texture as the total power in watts of a light with
+    /// surface `area`, which is radiance × area × π for a one-sided
+    /// uniform emitter, instead of as radiance. The radiance then follows
+    /// from the sides and falloff, so changing either keeps the power.
+    pub fn with_power(mut self, area: Float) -> Self {
+        self.power_area = Some(area);
+        self
     }
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord, _srec: &mut ScatterRecord) -> bool {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
         false
     }
 
-    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Point3) -> Color {
-        if rec.front_face {
-            self.emit.value(u, v, p)
-        } else {
-            Color::default()
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Point3) -> Color {
+        if !rec.front_face && !self.two_sided {
+            return Color::default();
         }
+        let mut scale = 1.0;
+        if !matches!(self.falloff, Falloff::Uniform) {
+            // `rec.normal` faces the ray, whichever side it hit
+            scale *= self
+                .falloff
+                .scale(rec.normal.dot(&-ray_in.direction().unit_vector()));
+        }
+        if let Some(area) = self.power_area {
+            let sides = if self.two_sided { 2.0 } else { 1.0 };
+            scale /= area * sides * self.projected_solid_angle;
+        }
+        scale * self.emit.value(u, v, p)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
     }
 }
 
+/// The phase function of a medium that scatters alike in every direction.
 pub struct Isotropic {
     albedo: Arc<dyn Texture>,
 }
@@ -186,14 +925,205 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
-        srec.specular_ray = Some(Ray::new(
-            rec.p,
-            Vec3::random_in_unit_sphere(),
-            ray_in.time(),
-        ));
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.specular_ray = None;
         srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
-        srec.pdf_ptr = None;
+        srec.pdf_ptr = Some(Box::new(HenyeyGreensteinPdf::new(&ray_in.direction(), 0.0)));
         true
     }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Float {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// The Henyey-Greenstein phase function of a medium, scattering forward
+/// for a positive asymmetry `g` and backward for a negative one.
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: Float,
+}
+
+impl HenyeyGreenstein {
+    /// `g` is clamped to [-0.99, 0.99], beyond which the lobe degenerates.
+    pub fn new_with_color(color: Color, g: Float) -> Self {
+        Self::new_with_texture(Arc::new(SolidColor::from(color)), g)
+    }
+
+    pub fn new_with_texture(texture: Arc<dyn Texture>, g: Float) -> Self {
+        Self {
+            albedo: texture,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.specular_ray = None;
+        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.pdf_ptr = Some(Box::new(HenyeyGreensteinPdf::new(
+            &ray_in.direction(),
+            self.g,
+        )));
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> Float {
+        let cos = ray_in
+            .direction()
+            .unit_vector()
+            .dot(&scattered.direction().unit_vector());
+        henyey_greenstein(cos, self.g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Principled, SamplerKind};
+
+    fn normal_hit() -> HitRecord<'static> {
+        HitRecord {
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            front_face: true,
+            ..Default::default()
+        }
+    }
+
+    /// The light `material` scatters by density towards `wo` from light
+    /// arriving evenly from the whole hemisphere, by quadrature of `bsdf`
+    /// with every lobe of a base of color `albedo`.
+    fn albedo_by_quadrature(material: &dyn Material, albedo: Color, wo: Vec3) -> Color {
+        let rec = normal_hit();
+        let ray_in = Ray::new(wo, -wo, 0.0);
+        let srec = ScatterRecord {
+            attenuation: albedo,
+            ..Default::default()
+        };
+        let n = 200;
+        let (d_theta, d_phi) = (PI / 2.0 / n as Float, PI / n as Float);
+        let mut sum = Color::default();
+        for i in 0..n {
+            let theta = (i as Float + 0.5) * d_theta;
+            for j in 0..2 * n {
+                let phi = (j as Float + 0.5) * d_phi;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let scattered = Ray::new(rec.p, direction, 0.0);
+                sum +=
+                    material.bsdf(&ray_in, &rec, &srec, &scattered) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    /// The same as estimated by `scatter`, its pdf and `bsdf`, as the
+    /// integrator does, with specular scattering counting as none.
+    fn albedo_by_sampling(material: &dyn Material, wo: Vec3) -> Color {
+        let rec = normal_hit();
+        let ray_in = Ray::new(wo, -wo, 0.0);
+        let mut sampler = SamplerKind::Independent.build(1, 1);
+        let count = 200_000;
+        let mut sum = Color::default();
+        for i in 0..count {
+            sampler.start_pixel_sample(i, 0, 0);
+            let mut srec = ScatterRecord::default();
+            if !material.scatter(&ray_in, &rec, &mut srec, &mut *sampler)
+                || srec.specular_ray.is_some()
+            {
+                continue;
+            }
+            let pdf = srec.pdf_ptr.as_deref().unwrap();
+            let scattered = Ray::new(rec.p, pdf.generate(&mut *sampler), 0.0);
+            let pdf_val = pdf.value(&scattered.direction());
+            if pdf_val > 0.0 {
+                sum += material.bsdf(&ray_in, &rec, &srec, &scattered)
+                    / (srec.lobe_probability * pdf_val);
+            }
+        }
+        sum / count as Float
+    }
+
+    #[test]
+    fn coated_materials_sample_their_bsdf_without_bias() {
+        let albedo = Color::new(0.2, 0.5, 0.8);
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new_with_color(albedo));
+        let smooth: Arc<dyn Material> = Arc::new(CoatedMaterial::new(diffuse.clone(), 1.5, 0.0));
+        let materials = [
+            CoatedMaterial::new(diffuse.clone(), 1.5, 0.3),
+            CoatedMaterial::new(smooth.clone(), 1.5, 0.0),
+            // both lobes by density over a base that picks among its own
+            CoatedMaterial::new(smooth, 1.5, 0.4),
+        ];
+        for material in &materials {
+            for wo in [Vec3::new(0.6, 0.0, 0.8), Vec3::new(0.95, 0.0, 0.312)] {
+                let expected = albedo_by_quadrature(material, albedo, wo);
+                let estimate = albedo_by_sampling(material, wo);
+                let error = (estimate - expected).length() / expected.length();
+                assert!(error < 0.01, "{estimate:?} != {expected:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn coats_pass_on_dispersion_of_their_base() {
+        let glass: Arc<dyn Material> = Arc::new(Dielectric::new_dispersive(Dispersion::BK7));
+        let coated = CoatedMaterial::new(glass, 1.5, 0.0);
+        let rec = HitRecord {
+            wavelength: Some(500.0),
+            ..normal_hit()
+        };
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let ray_in = Ray::new(wo, -wo, 0.0);
+        let mut sampler = SamplerKind::Independent.build(1, 1);
+        let mut through_base = 0;
+        for i in 0..100 {
+            sampler.start_pixel_sample(i, 0, 0);
+            let mut srec = ScatterRecord::default();
+            assert!(coated.scatter(&ray_in, &rec, &mut srec, &mut *sampler));
+            // the glass refracts most of the light, the layer reflects the
+            // rest without dispersion
+            let refracted = srec.specular_ray.unwrap().direction().z() < 0.0;
+            if refracted {
+                through_base += 1;
+                assert!(srec.dispersive);
+            }
+        }
+        assert!(through_base > 50);
+    }
+
+    #[test]
+    fn wrappers_forward_the_interior_of_their_glass() {
+        let glass = Dielectric::new(1.33).with_priority(2);
+        let interior = *glass.interior().unwrap();
+        let glass: Arc<dyn Material> = Arc::new(glass);
+        let diffuse: Arc<dyn Material> = Arc::new(Lambertian::new_with_color(Color::default()));
+        let weight = Arc::new(SolidColor::new(0.5, 0.5, 0.5));
+
+        let coated = CoatedMaterial::new(glass.clone(), 1.5, 0.0);
+        assert_eq!(coated.interior(), Some(&interior));
+        let mix = MixMaterial::new(diffuse.clone(), glass, weight.clone());
+        assert_eq!(mix.interior(), Some(&interior));
+        let mix = MixMaterial::new(diffuse.clone(), diffuse, weight);
+        assert_eq!(mix.interior(), None);
+
+        let principled = Principled::default().with_absorption(Color::new(1.0, 0.0, 0.0));
+        assert_eq!(principled.interior().map(|i| i.ior), Some(1.5));
+    }
 }
@@ -0,0 +1,167 @@
+use std::ops::Mul;
+
+use crate::{Float, Point3, Vec3};
+
+/// A 4x4 row major matrix of homogeneous coordinates, used for affine
+/// transforms of points, directions and normals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4([[Float; 4]; 4]);
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Self = Self([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub const fn new(rows: [[Float; 4]; 4]) -> Self {
+        Self(rows)
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        Self([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        Self([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Counterclockwise rotation by `angle` degrees about `axis`, looking
+    /// down the axis towards the origin.
+    pub fn rotation(axis: Vec3, angle: Float) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let t = 1.0 - cos;
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Places an object at `from` with its +z axis pointing at `to` and its
+    /// +y axis as close to `up` as possible.
+    pub fn look_at(from: Point3, to: Point3, up: Vec3) -> Self {
+        let w = (to - from).unit_vector();
+        let u = up.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Self([
+            [u.x(), v.x(), w.x(), from.x()],
+            [u.y(), v.y(), w.y(), from.y()],
+            [u.z(), v.z(), w.z(), from.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let m = &self.0;
+        Self(std::array::from_fn(|i| std::array::from_fn(|j| m[j][i])))
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting. Returns `None` for
+    /// singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut m = self.0;
+        let mut inv = Self::IDENTITY.0;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))
+                .unwrap();
+            if m[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / m[col][col];
+            for j in 0..4 {
+                m[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = m[row][col];
+                for j in 0..4 {
+                    m[row][j] -= factor * m[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+        Some(Self(inv))
+    }
+
+    /// Determinant of the upper left 3x3 block, the volume scale of the
+    /// transform.
+    pub fn linear_determinant(&self) -> Float {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.0;
+        let row = |r: &[Float; 4]| r[0] * p.x() + r[1] * p.y() + r[2] * p.z() + r[3];
+        let w = row(&m[3]);
+        let p = Point3::new(row(&m[0]), row(&m[1]), row(&m[2]));
+        if w == 1.0 {
+            p
+        } else {
+            p / w
+        }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.0;
+        let row = |r: &[Float; 4]| r[0] * v.x() + r[1] * v.y() + r[2] * v.z();
+        Vec3::new(row(&m[0]), row(&m[1]), row(&m[2]))
+    }
+}
+
+/// `a * b` applies `b` first, then `a`.
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (&self.0, &rhs.0);
+        Self(std::array::from_fn(|i| {
+            std::array::from_fn(|j| (0..4).map(|k| a[i][k] * b[k][j]).sum())
+        }))
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{Color, Float, Onb, Pdf, Sampler, Vec3, PI};
+
+/// Below this `alpha` a surface is treated as perfectly smooth.
+const SMOOTH_ALPHA: Float = 1e-3;
+
+/// The isotropic Trowbridge-Reitz (GGX) distribution of microfacet normals
+/// with Smith masking-shadowing. Directions are in a local frame with the
+/// macro surface normal along +z.
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha: Float,
+}
+
+impl TrowbridgeReitz {
+    /// `roughness` in [0, 1] is squared into `alpha`, which makes it look
+    /// perceptually linear.
+    pub fn new(roughness: Float) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: roughness * roughness,
+        }
+    }
+
+    pub fn alpha(&self) -> Float {
+        self.alpha
+    }
+
+    /// Whether the surface is smooth enough to reflect and refract like a
+    /// mirror.
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals `wm`, over the projected area.
+    pub fn d(&self, wm: &Vec3) -> Float {
+        let a2 = self.alpha * self.alpha;
+        let t = wm.z() * wm.z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    fn lambda(&self, w: &Vec3) -> Float {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return Float::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    /// Fraction of the microfacets facing `w` that are visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets that are visible from both `wo` and `wi`.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> Float {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, over solid angle.
+    pub fn visible_d(&self, wo: &Vec3, wm: &Vec3) -> Float {
+        let cos_o = wo.z().abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / cos_o * self.d(wm) * wo.dot(wm).max(0.0)
+    }
+
+    /// Samples a normal visible from `wo`, after Heitz, "Sampling the GGX
+    /// Distribution of Visible Normals".
+    pub fn sample_wm(&self, wo: &Vec3, (u1, u2): (Float, Float)) -> Vec3 {
+        // stretch to the hemisphere configuration
+        let mut wh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).unit_vector();
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vector()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // a uniform point on the disk, squeezed onto the visible half
+        let r = u1.sqrt();
+        let (sin, cos) = (2.0 * PI * u2).sin_cos();
+        let px = r * cos;
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        let py = (1.0 - s) * h + s * r * sin;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).unit_vector()
+    }
+}
+
+/// Fresnel reflectance of a dielectric interface for light arriving at
+/// `cos_i` to the normal, with `eta` the index on the far side of the
+/// normal over the index on its side.
+pub fn fresnel_dielectric(cos_i: Float, eta: Float) -> Float {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / eta)
+    } else {
+        (cos_i.min(1.0), eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: Float,
+    im: Float,
+}
+
+impl Complex {
+    fn new(re: Float, im: Float) -> Self {
+        Self { re, im }
+    }
+
+    fn add(self, b: Self) -> Self {
+        Self::new(self.re + b.re, self.im + b.im)
+    }
+
+    fn sub(self, b: Self) -> Self {
+        Self::new(self.re - b.re, self.im - b.im)
+    }
+
+    fn mul(self, b: Self) -> Self {
+        Self::new(
+            self.re * b.re - self.im * b.im,
+            self.re * b.im + self.im * b.re,
+        )
+    }
+
+    fn div(self, b: Self) -> Self {
+        let scale = 1.0 / (b.re * b.re + b.im * b.im);
+        Self::new(
+            (self.re * b.re + self.im * b.im) * scale,
+            (self.im * b.re - self.re * b.im) * scale,
+        )
+    }
+
+    fn norm(self) -> Float {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn sqrt(self) -> Self {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return self;
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Self::new(t1, t2)
+        } else {
+            Self::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction
+/// `eta + i k`, for one wavelength.
+fn fresnel_complex(cos_i: Float, eta: Float, k: Float) -> Float {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let eta = Complex::new(eta, k);
+    let cos = Complex::new(cos_i, 0.0);
+    let sin2_i = Complex::new(1.0 - cos_i * cos_i, 0.0);
+    let sin2_t = sin2_i.div(eta.mul(eta));
+    let cos_t = Complex::new(1.0, 0.0).sub(sin2_t).sqrt();
+    let r_parallel = eta.mul(cos).sub(cos_t).div(eta.mul(cos).add(cos_t));
+    let r_perpendicular = cos.sub(eta.mul(cos_t)).div(cos.add(eta.mul(cos_t)));
+    (r_parallel.norm() + r_perpendicular.norm()) / 2.0
+}
+
+/// Fresnel reflectance of a conductor, per color channel.
+pub fn fresnel_conductor(cos_i: Float, eta: &Color, k: &Color) -> Color {
+    Color::new(
+        fresnel_complex(cos_i, eta.x(), k.x()),
+        fresnel_complex(cos_i, eta.y(), k.y()),
+        fresnel_complex(cos_i, eta.z(), k.z()),
+    )
+}
+
+/// Scattering off a rough interface, sampled through the normals visible
+/// from the outgoing direction. Conductors only reflect; dielectrics, given
+/// the relative index `eta` of the far side of the surface, also refract,
+/// choosing in proportion to the Fresnel reflectance.
+pub struct MicrofacetPdf {
+    uvw: Onb,
+    /// Direction towards the viewer, in the local frame.
+    wo: Vec3,
+    distribution: TrowbridgeReitz,
+    eta: Option<Float>,
+}
+
+impl MicrofacetPdf {
+    /// `normal` must face `wo`, as the normal of a `HitRecord` faces the
+    /// ray.
+    pub fn new(
+        normal: &Vec3,
+        wo: &Vec3,
+        distribution: TrowbridgeReitz,
+        eta: Option<Float>,
+    ) -> Self {
+        let uvw = Onb::from(normal);
+        let mut wo = uvw.to_local(&wo.unit_vector());
+        // rounding can put grazing directions just behind the normal
+        if wo.z() <= 0.0 {
+            wo = Vec3::new(wo.x(), wo.y(), 1e-6);
+        }
+        Self {
+            uvw,
+            wo,
+            distribution,
+            eta,
+        }
+    }
+
+    /// The microfacet normal, facing +z, that scatters `wo` into the local
+    /// `wi`, and the relative index along the way, 1 for a reflection.
+    fn half_vector(&self, wi: &Vec3) -> Option<(Vec3, Float)> {
+        let etap = if wi.z() > 0.0 {
+            1.0
+        } else if wi.z() < 0.0 {
+            self.eta?
+        } else {
+            return None;
+        };
+        let wm = *wi * etap + self.wo;
+        if wm.length_squared() == 0.0 {
+            return None;
+        }
+        let mut wm = wm.unit_vector();
+        if wm.z() < 0.0 {
+            wm = -wm;
+        }
+        // microfacets seen from behind scatter nothing
+        if wm.dot(wi) * wi.z() < 0.0 || wm.dot(&self.wo) < 0.0 {
+            return None;
+        }
+        Some((wm, etap))
+    }
+
+    /// The BSDF times the cosine to the normal for light arriving along
+    /// `direction`, where `fresnel(cos)` is the reflectance of a microfacet
+    /// lit at `cos` to its normal.
+    pub fn scattering(&self, direction: &Vec3, fresnel: impl Fn(Float) -> Color) -> Color {
+        let wi = self.uvw.to_local(&direction.unit_vector());
+        let Some((wm, etap)) = self.half_vector(&wi) else {
+            return Color::default();
+        };
+        let wo = &self.wo;
+        let d_g = self.distribution.d(&wm) * self.distribution.g(wo, &wi);
+        let cos_om = wo.dot(&wm);
+        if etap == 1.0 {
+            d_g / (4.0 * wo.z()) * fresnel(cos_om)
+        } else {
+            let denom = wi.dot(&wm) + cos_om / etap;
+            let denom = denom * denom;
+            let transmitted = d_g * (wi.dot(&wm) * cos_om).abs() / (wo.z() * denom);
+            // radiance is compressed into the narrower cone of the denser
+            // side
+            (Color::new(1.0, 1.0, 1.0) - fresnel(cos_om)) * transmitted / (etap * etap)
+        }
+    }
+}
+
+impl Pdf for MicrofacetPdf {
+    fn value(&self, direction: &Vec3) -> Float {
+        if direction.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wi = self.uvw.to_local(&direction.unit_vector());
+        let Some((wm, etap)) = self.half_vector(&wi) else {
+            return 0.0;
+        };
+        let visible = self.distribution.visible_d(&self.wo, &wm);
+        let cos_om = self.wo.dot(&wm);
+        let Some(eta) = self.eta else {
+            return visible / (4.0 * cos_om);
+        };
+        let reflectance = fresnel_dielectric(cos_om, eta);
+        if etap == 1.0 {
+            visible / (4.0 * cos_om) * reflectance
+        } else {
+            let denom = wi.dot(&wm) + cos_om / etap;
+            visible * wi.dot(&wm).abs() / (denom * denom) * (1.0 - reflectance)
+        }
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let wo = self.wo;
+        let wm = self.distribution.sample_wm(&wo, sampler.get_2d());
+        let cos_om = wo.dot(&wm);
+        let (wi, reflection) = match self.eta {
+            Some(eta) if sampler.get_1d() >= fresnel_dielectric(cos_om, eta) => {
+                let sin2_t = (1.0 - cos_om * cos_om).max(0.0) / (eta * eta);
+                let cos_t = (1.0 - sin2_t).max(0.0).sqrt();
+                (-wo / eta + (cos_om / eta - cos_t) * wm, false)
+            }
+            _ => (-wo + 2.0 * cos_om * wm, true),
+        };
+        // a reflection below the surface or a refraction above it would be
+        // mistaken for the other event; the zero vector has no density and
+        // ends the path instead
+        if (wi.z() > 0.0) != reflection || wi.z() == 0.0 {
+            return Vec3::default();
+        }
+        self.uvw.local_v(&wi)
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    scene::strip_comment, Color, Dielectric, DiffuseLight, Float, ImageTexture, Lambertian,
+    Material, Mesh, MeshFace, Metal, Point3, Principled, SolidColor, Texture, Vec3,
+};
+
+fn invalid(path: &Path, line: usize, message: impl Display) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidData,
+        format!("{}:{}: {}", path.display(), line, message),
+    )
+}
+
+fn floats<const N: usize>(args: &[&str], path: &Path, line: usize) -> io::Result<[Float; N]> {
+    let mut values = [0.0; N];
+    if args.len() < N {
+        return Err(invalid(path, line, format!("expected {} numbers", N)));
+    }
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg
+            .parse()
+            .map_err(|_| invalid(path, line, format!("invalid number `{}`", arg)))?;
+    }
+    Ok(values)
+}
+
+/// Material parameters of one `newmtl` block of an MTL file.
+struct MtlMaterial {
+    diffuse: Color,
+    diffuse_map: Option<Arc<dyn Texture>>,
+    specular: Color,
+    shininess: Float,
+    emission: Color,
+    ior: Float,
+    dissolve: Float,
+    illum: u32,
+    /// The physically based extension: `Pr`, `Pm`, `Ps` and `Pc`.
+    roughness: Option<Float>,
+    metallic: Option<Float>,
+    sheen: Option<Float>,
+    clearcoat: Option<Float>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            specular: Color::default(),
+            shininess: 0.0,
+            emission: Color::default(),
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            clearcoat: None,
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Maps the Phong style parameters onto the closest material we have:
+    /// emissive materials become lights, transparent ones glass, mirror like
+    /// ones metal and everything else lambertian. Materials using the
+    /// physically based extension become principled instead.
+    fn build(self) -> Arc<dyn Material> {
+        let max = |c: &Color| c.x().max(c.y()).max(c.z());
+        let pbr = [self.roughness, self.metallic, self.sheen, self.clearcoat];
+        if pbr.iter().any(Option::is_some) {
+            let gray = |v: Float| -> Arc<dyn Texture> { Arc::new(SolidColor::new(v, v, v)) };
+            Arc::new(Principled {
+                base_color: self
+                    .diffuse_map
+                    .unwrap_or_else(|| Arc::new(SolidColor::from(self.diffuse))),
+                metallic: gray(self.metallic.unwrap_or(0.0)),
+                roughness: gray(self.roughness.unwrap_or(0.5)),
+                sheen: gray(self.sheen.unwrap_or(0.0)),
+                clearcoat: gray(self.clearcoat.unwrap_or(0.0)),
+                transmission: gray(1.0 - self.dissolve),
+                emission: (max(&self.emission) > 0.0)
+                    .then(|| Arc::new(SolidColor::from(self.emission)) as _),
+                ior: self.ior,
+                ..Default::default()
+            })
+        } else if max(&self.emission) > 0.0 {
+            Arc::new(DiffuseLight::new_with_color(self.emission))
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectric::new(self.ior))
+        } else if self.illum == 3 || (max(&self.specular) > 0.0 && max(&self.diffuse) == 0.0) {
+            // invert the Phong exponent to a fuzz radius
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else if let Some(texture) = self.diffuse_map {
+            Arc::new(Lambertian::new_with_shared_texture(texture))
+        } else {
+            Arc::new(Lambertian::new_with_color(self.diffuse))
+        }
+    }
+}
+
+fn load_mtl(path: &Path) -> io::Result<HashMap<String, Arc<dyn Material>>> {
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let mut tokens = strip_comment(line).split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material.build());
+            }
+            current = Some((args.join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let Some((_, material)) = current.as_mut() else {
+            return Err(invalid(path, line_no, "statement before `newmtl`"));
+        };
+        let color = |args: &[&str]| -> io::Result<Color> {
+            let [r, g, b] = floats(args, path, line_no)?;
+            Ok(Color::new(r, g, b))
+        };
+        match keyword {
+            "Kd" => material.diffuse = color(&args)?,
+            "Ks" => material.specular = color(&args)?,
+            "Ke" => material.emission = color(&args)?,
+            "Ns" => material.shininess = floats::<1>(&args, path, line_no)?[0],
+            "Ni" => material.ior = floats::<1>(&args, path, line_no)?[0],
+            "d" => material.dissolve = floats::<1>(&args, path, line_no)?[0],
+            "Tr" => material.dissolve = 1.0 - floats::<1>(&args, path, line_no)?[0],
+            "illum" => material.illum = floats::<1>(&args, path, line_no)?[0] as u32,
+            "Pr" => material.roughness = Some(floats::<1>(&args, path, line_no)?[0]),
+            "Pm" => material.metallic = Some(floats::<1>(&args, path, line_no)?[0]),
+            "Ps" => material.sheen = Some(floats::<1>(&args, path, line_no)?[0]),
+            "Pc" => material.clearcoat = Some(floats::<1>(&args, path, line_no)?[0]),
+            "map_Kd" => {
+                // options such as `-s` are not supported, the file name is last
+                let file = args
+                    .last()
+                    .ok_or_else(|| invalid(path, line_no, "missing texture file"))?;
+                let texture = ImageTexture::open(base_dir.join(file))
+                    .map_err(|e| invalid(path, line_no, e))?;
+                material.diffuse_map = Some(Arc::new(texture));
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material.build());
+    }
+    Ok(materials)
+}
+
+/// Resolves a 1-based, possibly negative (relative to the end) OBJ index.
+fn resolve_index(index: &str, count: usize, path: &Path, line: usize) -> io::Result<usize> {
+    let i: isize = index
+        .parse()
+        .map_err(|_| invalid(path, line, format!("invalid index `{}`", index)))?;
+    let resolved = if i < 0 { count as isize + i } else { i - 1 };
+    if resolved < 0 || resolved as usize >= count {
+        return Err(invalid(path, line, format!("index {} out of range", i)));
+    }
+    Ok(resolved as usize)
+}
+
+/// Per vertex attributes are only used if all corners of a face have them.
+fn all_some(indices: [Option<usize>; 3]) -> Option<[usize; 3]> {
+    match indices {
+        [Some(a), Some(b), Some(c)] => Some([a, b, c]),
+        _ => None,
+    }
+}
+
+/// Loads a Wavefront OBJ file with its MTL material libraries. Polygons are
+/// split into triangle fans, and faces without a material use
+/// `default_material`.
+pub fn load_obj(path: impl AsRef<Path>, default_material: Arc<dyn Material>) -> io::Result<Mesh> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut mesh = Mesh {
+        materials: vec![default_material],
+        ..Mesh::default()
+    };
+    let mut library: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let mut tokens = strip_comment(line).split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => {
+                let [x, y, z] = floats(&args, path, line_no)?;
+                mesh.positions.push(Point3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = floats(&args, path, line_no)?;
+                mesh.normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                // `v` defaults to 0, a `w` is ignored
+                let [u, v] = match args.len() {
+                    1 => [floats::<1>(&args, path, line_no)?[0], 0.0],
+                    _ => floats(&args, path, line_no)?,
+                };
+                mesh.uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(invalid(path, line_no, "faces need at least 3 vertices"));
+                }
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let vertex =
+                        resolve_index(parts.next().unwrap(), mesh.positions.len(), path, line_no)?;
+                    let uv = match parts.next() {
+                        Some(i) if !i.is_empty() => {
+                            Some(resolve_index(i, mesh.uvs.len(), path, line_no)?)
+                        }
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(i) if !i.is_empty() => {
+                            Some(resolve_index(i, mesh.normals.len(), path, line_no)?)
+                        }
+                        _ => None,
+                    };
+                    corners.push((vertex, uv, normal));
+                }
+                for i in 1..corners.len() - 1 {
+                    let tri = [corners[0], corners[i], corners[i + 1]];
+                    mesh.faces.push(MeshFace {
+                        vertices: tri.map(|c| c.0),
+                        uvs: all_some(tri.map(|c| c.1)),
+                        normals: all_some(tri.map(|c| c.2)),
+                        material: current_material,
+                    });
+                }
+            }
+            "mtllib" => {
+                for file in &args {
+                    let materials =
+                        load_mtl(&base_dir.join(file)).map_err(|e| invalid(path, line_no, e))?;
+                    library.extend(materials);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_material = match material_indices.get(&name) {
+                    Some(index) => *index,
+                    None => {
+                        let material = library.get(&name).cloned().ok_or_else(|| {
+                            invalid(path, line_no, format!("unknown material `{}`", name))
+                        })?;
+                        mesh.materials.push(material);
+                        material_indices.insert(name, mesh.materials.len() - 1);
+                        mesh.materials.len() - 1
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+
+    if mesh.faces.is_empty() {
+        return Err(invalid(path, source.lines().count(), "no faces"));
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes `files` into a fresh directory named after `test` and
+    /// returns it.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obj-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new_with_color(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn load_error(test: &str, obj: &str) -> String {
+        let dir = write_files(test, &[("mesh.obj", obj)]);
+        match load_obj(dir.join("mesh.obj"), gray()) {
+            Ok(_) => panic!("{obj:?} loaded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn loads_faces_and_vertex_attributes() {
+        let dir = write_files(
+            "faces",
+            &[(
+                "mesh.obj",
+                "# a unit quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0 # trailing comment
+vt 0 0
+vt 1
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+f -4//1 -3//1 -2//1
+",
+            )],
+        );
+        let mesh = load_obj(dir.join("mesh.obj"), gray()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.uvs[1], (1.0, 0.0));
+        assert_eq!(mesh.normals, [Vec3::new(0.0, 0.0, 1.0)]);
+        // the quad is split into a fan of two triangles
+        assert_eq!(mesh.faces.len(), 3);
+        assert_eq!(mesh.faces[0].vertices, [0, 1, 2]);
+        assert_eq!(mesh.faces[1].vertices, [0, 2, 3]);
+        assert_eq!(mesh.faces[1].uvs, Some([0, 2, 3]));
+        assert_eq!(mesh.faces[1].normals, Some([0, 0, 0]));
+        // negative indices count from the end, without uvs
+        assert_eq!(mesh.faces[2].vertices, [0, 1, 2]);
+        assert_eq!(mesh.faces[2].uvs, None);
+        assert!(mesh.faces.iter().all(|face| face.material == 0));
+    }
+
+    #[test]
+    fn loads_mtl_materials() {
+        let dir = write_files(
+            "mtl",
+            &[
+                (
+                    "mesh.obj",
+                    "mtllib mesh.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+usemtl lamp
+f 1 2 3
+usemtl glass pane
+f 1 2 3
+usemtl lamp
+f 1 2 3
+",
+                ),
+                (
+                    "mesh.mtl",
+                    "newmtl lamp
+Ke 4 4 4
+newmtl glass pane
+Ni 1.33
+d 0.5
+",
+                ),
+            ],
+        );
+        let mesh = load_obj(dir.join("mesh.obj"), gray()).unwrap();
+        let materials: Vec<usize> = mesh.faces.iter().map(|face| face.material).collect();
+        assert_eq!(materials, [0, 1, 2, 1]);
+        assert_eq!(mesh.materials.len(), 3);
+        assert!(mesh.materials[1].is_emissive());
+        assert!(!mesh.materials[2].is_emissive());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let triangle = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        let error = load_error("range", &format!("{triangle}f 1 2 4\n"));
+        assert!(error.ends_with(":4: index 4 out of range"), "{error}");
+        let error = load_error("negative", &format!("{triangle}f -1 -2 -4\n"));
+        assert!(error.contains("index -4 out of range"), "{error}");
+        let error = load_error("material", &format!("{triangle}usemtl nope\nf 1 2 3\n"));
+        assert!(error.contains(":4: unknown material `nope`"), "{error}");
+        let error = load_error("number", "v 0 x 0\n");
+        assert!(error.contains(":1: invalid number `x`"), "{error}");
+        let error = load_error("short", &format!("{triangle}f 1 2\n"));
+        assert!(error.contains("at least 3 vertices"), "{error}");
+        assert!(load_error("empty", triangle).contains("no faces"));
+    }
+}
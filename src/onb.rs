@@ -25,6 +25,15 @@ impl Onb {
     pub fn local_v(&self, v: &Vec3) -> Vec3 {
         self.local(v.x(), v.y(), v.z())
     }
+
+    /// The coordinates of `v` in this basis, the inverse of `local_v`.
+    pub fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(&self.axis[0]),
+            v.dot(&self.axis[1]),
+            v.dot(&self.axis[2]),
+        )
+    }
 }
 
 impl From<&Vec3> for Onb {
@@ -1,10 +1,8 @@
-use rand::random;
-
-use crate::{Float, Hittable, Onb, Point3, Vec3, PI};
+use crate::{Environment, Float, Hittable, Onb, Point3, Sampler, Vec3, PI};
 
 pub trait Pdf {
     fn value(&self, direction: &Vec3) -> Float;
-    fn generate(&self) -> Vec3;
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3;
 }
 
 pub struct CosinePdf {
@@ -27,8 +25,53 @@ impl Pdf for CosinePdf {
         }
     }
 
-    fn generate(&self) -> Vec3 {
-        self.uvw.local_v(&Vec3::random_cosine_direction())
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.uvw.local_v(&Vec3::random_cosine_direction(sampler))
+    }
+}
+
+/// The Henyey-Greenstein phase function: the density of scattering by
+/// an angle with cosine `cos` from the direction of travel, for the
+/// asymmetry `g` in (-1, 1). Positive `g` scatters forward, negative `g`
+/// backward and zero alike in every direction.
+pub fn henyey_greenstein(cos: Float, g: Float) -> Float {
+    let denominator = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+/// Directions scattered by the Henyey-Greenstein phase function from a ray
+/// travelling along `direction`.
+pub struct HenyeyGreensteinPdf {
+    uvw: Onb,
+    g: Float,
+}
+
+impl HenyeyGreensteinPdf {
+    pub fn new(direction: &Vec3, g: Float) -> Self {
+        Self {
+            uvw: Onb::from(&direction.unit_vector()),
+            g,
+        }
+    }
+}
+
+impl Pdf for HenyeyGreensteinPdf {
+    fn value(&self, direction: &Vec3) -> Float {
+        henyey_greenstein(direction.unit_vector().dot(&self.uvw.w()), self.g)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.get_2d();
+        let g = self.g;
+        let cos = if g.abs() < 1e-3 {
+            1.0 - 2.0 * r2
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r2);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let (sin_phi, cos_phi) = (2.0 * PI * r1).sin_cos();
+        self.uvw.local(sin * cos_phi, sin * sin_phi, cos)
     }
 }
 
@@ -48,31 +91,114 @@ impl Pdf for HittablePdf<'_> {
         self.ptr.pdf_value(&self.o, direction)
     }
 
-    fn generate(&self) -> Vec3 {
-        self.ptr.random(&self.o)
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.ptr.random(&self.o, sampler)
+    }
+}
+
+/// Directions towards the environment, as sampled by the environment.
+pub struct EnvironmentPdf<'a> {
+    environment: &'a dyn Environment,
+}
+
+impl<'a> EnvironmentPdf<'a> {
+    pub fn new(environment: &'a dyn Environment) -> Self {
+        Self { environment }
     }
 }
 
+impl Pdf for EnvironmentPdf<'_> {
+    fn value(&self, direction: &Vec3) -> Float {
+        self.environment.pdf_value(&direction.unit_vector())
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.environment.random(sampler)
+    }
+}
+
+/// A weighted mixture of pdfs. Each component is sampled with probability
+/// proportional to its weight, and the weights need not sum to one.
 pub struct MixturePdf<'a> {
-    p: [&'a dyn Pdf; 2],
+    components: Vec<(&'a dyn Pdf, Float)>,
+    total_weight: Float,
 }
 
 impl<'a> MixturePdf<'a> {
-    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
-        Self { p: [p0, p1] }
+    /// # Panics
+    ///
+    /// If no weight is positive.
+    pub fn new(components: Vec<(&'a dyn Pdf, Float)>) -> Self {
+        let components: Vec<_> = components.into_iter().filter(|&(_, w)| w > 0.0).collect();
+        if components.is_empty() {
+            panic!("MixturePdf needs a component with a positive weight");
+        }
+        let total_weight = components.iter().map(|&(_, w)| w).sum();
+        Self {
+            components,
+            total_weight,
+        }
+    }
+
+    /// A mixture giving every pdf the same weight.
+    pub fn uniform(pdfs: &[&'a dyn Pdf]) -> Self {
+        Self::new(pdfs.iter().map(|&p| (p, 1.0)).collect())
     }
 }
 
 impl Pdf for MixturePdf<'_> {
     fn value(&self, direction: &Vec3) -> Float {
-        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+        self.components
+            .iter()
+            .map(|&(p, w)| w * p.value(direction))
+            .sum::<Float>()
+            / self.total_weight
     }
 
-    fn generate(&self) -> Vec3 {
-        if random::<Float>() < 0.5 {
-            self.p[0].generate()
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut u = sampler.get_1d() * self.total_weight;
+        for &(p, w) in &self.components {
+            if u < w {
+                return p.generate(sampler);
+            }
+            u -= w;
+        }
+        // only reached through rounding
+        self.components.last().unwrap().0.generate(sampler)
+    }
+}
+
+/// How multiple importance sampling weighs a sample against the other
+/// strategies that could have produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MisHeuristic {
+    Balance,
+    Power,
+}
+
+impl MisHeuristic {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "balance" => Some(Self::Balance),
+            "power" => Some(Self::Power),
+            _ => None,
+        }
+    }
+
+    /// Weight of a sample taken with density `pdf` when another strategy
+    /// would have taken it with density `other_pdf`.
+    pub fn weight(self, pdf: Float, other_pdf: Float) -> Float {
+        if pdf.is_infinite() {
+            return 1.0;
+        }
+        let (a, b) = match self {
+            Self::Balance => (pdf, other_pdf),
+            Self::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if a + b > 0.0 {
+            a / (a + b)
         } else {
-            self.p[1].generate()
+            0.0
         }
     }
 }
@@ -1,6 +1,6 @@
-use rand::{prelude::SliceRandom, thread_rng};
+use rand::prelude::SliceRandom;
 
-use crate::{Float, Point3, Vec3};
+use crate::{Float, Point3, Rng, Vec3};
 
 const POINT_COUNT: usize = 256;
 pub struct Perlin {
@@ -11,31 +11,24 @@ pub struct Perlin {
 }
 
 impl Perlin {
+    /// Noise with the default seed, the same in every run.
     pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = Rng::new(seed, 0);
         let mut perlin = Self {
             ranvec: (0..POINT_COUNT)
-                .map(|_| Vec3::random_range(-1.0..1.0).unit_vector())
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-            perm_x: (0..POINT_COUNT)
-                .into_iter()
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-            perm_y: (0..POINT_COUNT)
-                .into_iter()
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap(),
-            perm_z: (0..POINT_COUNT)
-                .into_iter()
+                .map(|_| Vec3::random_range(&mut rng, -1.0..1.0).unit_vector())
                 .collect::<Vec<_>>()
                 .try_into()
                 .unwrap(),
+            perm_x: (0..POINT_COUNT).collect::<Vec<_>>().try_into().unwrap(),
+            perm_y: (0..POINT_COUNT).collect::<Vec<_>>().try_into().unwrap(),
+            perm_z: (0..POINT_COUNT).collect::<Vec<_>>().try_into().unwrap(),
         };
 
-        let mut rng = thread_rng();
         perlin.perm_x.shuffle(&mut rng);
         perlin.perm_y.shuffle(&mut rng);
         perlin.perm_z.shuffle(&mut rng);
@@ -70,7 +63,7 @@ impl Perlin {
 
     pub fn turb(&self, p: &Point3, depth: u32) -> Float {
         let mut accum = 0.0;
-        let mut temp_p = p.clone();
+        let mut temp_p = *p;
         let mut weight = 1.0;
 
         for _ in 0..depth {
@@ -102,3 +95,9 @@ impl Perlin {
         accum
     }
 }
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use crate::{
+    fresnel_dielectric, material::relative_ior, tone_map::luminance, Color, CosinePdf, Float,
+    HitRecord, Interior, Material, MicrofacetPdf, MixturePdf, Pdf, Point3, Ray, Sampler,
+    ScatterRecord, SolidColor, Texture, TrowbridgeReitz, Vec3, PI,
+};
+
+/// Every lobe is glossy, a smoother surface would need a mirror lobe.
+const MIN_ROUGHNESS: Float = 0.05;
+/// Roughness of the clearcoat layer.
+const CLEARCOAT_ROUGHNESS: Float = 0.1;
+
+fn gray(value: Float) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(value, value, value))
+}
+
+fn lerp(a: Color, b: Color, t: Float) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+/// Schlick's approximation of the Fresnel reflectance with normal
+/// reflectance `f0`.
+fn schlick(f0: Color, cos: Float) -> Color {
+    let m = (1.0 - cos).clamp(0.0, 1.0);
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (m * m * m * m * m)
+}
+
+/// A Disney style "principled" material that blends a diffuse base with
+/// sheen, a specular layer, metal, glass and a clearcoat, so that assets
+/// from OBJ or glTF can share one material model.
+///
+/// Every parameter is a texture. Colors use all three channels, the scalar
+/// parameters in [0, 1] read the first channel, so a grayscale map or a
+/// `SolidColor` drives them.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    /// Blends from a dielectric to a metal tinted by the base color.
+    pub metallic: Arc<dyn Texture>,
+    /// Clamped to at least 0.05.
+    pub roughness: Arc<dyn Texture>,
+    /// Normal reflectance of the dielectric, 0.5 being 4%.
+    pub specular: Arc<dyn Texture>,
+    /// Tints the dielectric reflection towards the base color.
+    pub specular_tint: Arc<dyn Texture>,
+    /// Extra grazing reflection for cloth.
+    pub sheen: Arc<dyn Texture>,
+    /// Strength of a glossy, colorless coat.
+    pub clearcoat: Arc<dyn Texture>,
+    /// Blends the dielectric towards glass tinted by the base color.
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Option<Arc<dyn Texture>>,
+    /// Index of refraction of the glass.
+    pub ior: Float,
+    /// The medium behind the glass, for nesting and absorption, see
+    /// `Interior`.
+    pub interior: Option<Interior>,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Self {
+            base_color: gray(0.8),
+            metallic: gray(0.0),
+            roughness: gray(0.5),
+            specular: gray(0.5),
+            specular_tint: gray(0.0),
+            sheen: gray(0.0),
+            clearcoat: gray(0.0),
+            transmission: gray(0.0),
+            emission: None,
+            ior: 1.5,
+            interior: None,
+        }
+    }
+}
+
+impl Principled {
+    pub fn new_with_color(color: Color) -> Self {
+        Self {
+            base_color: Arc::new(SolidColor::from(color)),
+            ..Default::default()
+        }
+    }
+
+    /// Absorbs light travelling inside, per unit of length and channel.
+    pub fn with_absorption(mut self, absorption: Color) -> Self {
+        self.interior
+            .get_or_insert(Interior::new(self.ior))
+            .absorption = absorption;
+        self
+    }
+
+    /// Nests the glass among other dielectrics by `priority`, see
+    /// `Interior`.
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.interior
+            .get_or_insert(Interior::new(self.ior))
+            .priority = priority;
+        self
+    }
+
+    fn pdf(&self, ray_in: &Ray, rec: &HitRecord) -> PrincipledPdf {
+        let scalar = |t: &Arc<dyn Texture>| t.value(rec.u, rec.v, &rec.p).x().clamp(0.0, 1.0);
+        let base_color = self.base_color.value(rec.u, rec.v, &rec.p);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness).max(MIN_ROUGHNESS);
+        let transmission = scalar(&self.transmission);
+        let specular_tint = scalar(&self.specular_tint);
+
+        let white = Color::new(1.0, 1.0, 1.0);
+        let base_luminance = luminance(&base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color / base_luminance
+        } else {
+            white
+        };
+        let dielectric_f0 = 0.08 * scalar(&self.specular) * lerp(white, tint, specular_tint);
+        let eta = relative_ior(self.ior, rec);
+
+        let wo = -ray_in.direction().unit_vector();
+        let cos_o = rec.normal.dot(&wo);
+        let lobes = Lobes {
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            specular: 1.0 - (1.0 - metallic) * transmission,
+            glass: (1.0 - metallic) * transmission,
+            clearcoat: 0.25 * scalar(&self.clearcoat),
+        };
+        let f0 = lerp(dielectric_f0, base_color, metallic);
+        PrincipledPdf {
+            normal: rec.normal,
+            wo,
+            base_color,
+            roughness,
+            sheen: scalar(&self.sheen) * lerp(white, tint, 0.5),
+            f0,
+            eta,
+            diffuse: CosinePdf::new(&rec.normal),
+            specular: MicrofacetPdf::new(&rec.normal, &wo, TrowbridgeReitz::new(roughness), None),
+            glass: MicrofacetPdf::new(&rec.normal, &wo, TrowbridgeReitz::new(roughness), Some(eta)),
+            clearcoat: MicrofacetPdf::new(
+                &rec.normal,
+                &wo,
+                TrowbridgeReitz::new(CLEARCOAT_ROUGHNESS),
+                None,
+            ),
+            // pick lobes by their rough share of the reflected light
+            sample_weights: [
+                lobes.diffuse,
+                lobes.specular * luminance(&schlick(f0, cos_o)).max(0.05),
+                lobes.glass,
+                lobes.clearcoat * schlick(Color::new(0.04, 0.04, 0.04), cos_o).x(),
+            ],
+            lobes,
+        }
+    }
+}
+
+/// How much each lobe contributes.
+struct Lobes {
+    diffuse: Float,
+    specular: Float,
+    glass: Float,
+    clearcoat: Float,
+}
+
+/// The lobes of a `Principled` material at one point, sampled in
+/// proportion to their estimated contributions.
+struct PrincipledPdf {
+    normal: Vec3,
+    wo: Vec3,
+    base_color: Color,
+    roughness: Float,
+    sheen: Color,
+    f0: Color,
+    eta: Float,
+    diffuse: CosinePdf,
+    specular: MicrofacetPdf,
+    glass: MicrofacetPdf,
+    clearcoat: MicrofacetPdf,
+    lobes: Lobes,
+    sample_weights: [Float; 4],
+}
+
+impl PrincipledPdf {
+    fn mixture(&self) -> MixturePdf<'_> {
+        MixturePdf::new(vec![
+            (&self.diffuse, self.sample_weights[0]),
+            (&self.specular, self.sample_weights[1]),
+            (&self.glass, self.sample_weights[2]),
+            (&self.clearcoat, self.sample_weights[3]),
+        ])
+    }
+
+    /// The BSDF times the cosine to the normal, summed over the lobes.
+    fn scattering(&self, direction: &Vec3) -> Color {
+        if direction.length_squared() == 0.0 {
+            return Color::default();
+        }
+        let wi = direction.unit_vector();
+        let cos_i = self.normal.dot(&wi);
+        let mut result = Color::default();
+
+        if self.lobes.diffuse > 0.0 && cos_i > 0.0 {
+            // Burley's diffuse with its grazing retro-reflection
+            let cos_o = self.normal.dot(&self.wo).max(0.0);
+            let cos_d = (wi + self.wo).unit_vector().dot(&wi);
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let weight = |cos: Float| 1.0 + (fd90 - 1.0) * (1.0 - cos).powi(5);
+            let diffuse = self.base_color / PI * weight(cos_i) * weight(cos_o);
+            let sheen = self.sheen * (1.0 - cos_d).powi(5);
+            result += self.lobes.diffuse * (diffuse + sheen) * cos_i;
+        }
+        if self.lobes.specular > 0.0 {
+            result += self.lobes.specular
+                * self
+                    .specular
+                    .scattering(direction, |cos| schlick(self.f0, cos));
+        }
+        if self.lobes.glass > 0.0 {
+            let glass = self.glass.scattering(direction, |cos| {
+                let reflectance = fresnel_dielectric(cos, self.eta);
+                Color::new(reflectance, reflectance, reflectance)
+            });
+            let tint = if cos_i < 0.0 {
+                self.base_color
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            result += self.lobes.glass * tint * glass;
+        }
+        if self.lobes.clearcoat > 0.0 {
+            result += self.lobes.clearcoat
+                * self
+                    .clearcoat
+                    .scattering(direction, |cos| schlick(Color::new(0.04, 0.04, 0.04), cos));
+        }
+        result
+    }
+}
+
+impl Pdf for PrincipledPdf {
+    fn value(&self, direction: &Vec3) -> Float {
+        // the glossy lobes reject samples with the zero vector
+        if direction.length_squared() == 0.0 {
+            return 0.0;
+        }
+        self.mixture().value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        self.mixture().generate(sampler)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        srec: &mut ScatterRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> bool {
+        srec.specular_ray = None;
+        srec.attenuation = self.base_color.value(rec.u, rec.v, &rec.p);
+        srec.pdf_ptr = Some(Box::new(self.pdf(ray_in, rec)));
+        true
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Float {
+        self.pdf(ray_in, rec).value(&scattered.direction())
+    }
+
+    fn bsdf(&self, ray_in: &Ray, rec: &HitRecord, _srec: &ScatterRecord, scattered: &Ray) -> Color {
+        self.pdf(ray_in, rec).scattering(&scattered.direction())
+    }
+
+    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord, u: Float, v: Float, p: &Point3) -> Color {
+        match &self.emission {
+            Some(emission) if rec.front_face => emission.value(u, v, p),
+            _ => Color::default(),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emission.is_some()
+    }
+
+    fn interior(&self) -> Option<&Interior> {
+        self.interior.as_ref()
+    }
+}
@@ -5,6 +5,8 @@ pub struct Ray {
     orig: Vec3,
     dir: Vec3,
     tm: Float,
+    shadow: bool,
+    seed: u64,
 }
 
 impl Ray {
@@ -13,6 +15,33 @@ impl Ray {
             orig: origin,
             dir: direction,
             tm: time,
+            shadow: false,
+            seed: 0,
+        }
+    }
+
+    /// A ray that only tests for occluders: participating media do not
+    /// stop it but attenuate it through `Hittable::transmittance`.
+    pub fn new_shadow(origin: Point3, direction: Vec3, time: Float) -> Self {
+        Self {
+            shadow: true,
+            ..Self::new(origin, direction, time)
+        }
+    }
+
+    /// The same ray carrying `seed`, from which the decisions made along it
+    /// without a sampler at hand, such as free paths in media, are drawn.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// The same kind of ray at the same time and with the same seed, with
+    /// another origin and direction, as seen from the space of an instance.
+    pub fn transformed(&self, origin: Point3, direction: Vec3) -> Self {
+        Self {
+            orig: origin,
+            dir: direction,
+            ..*self
         }
     }
 
@@ -28,6 +57,14 @@ impl Ray {
         self.tm
     }
 
+    pub const fn is_shadow(&self) -> bool {
+        self.shadow
+    }
+
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn at(&self, t: Float) -> Point3 {
         self.orig + t * self.dir
     }
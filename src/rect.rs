@@ -1,4 +1,4 @@
-use crate::{random_range, Float, HitRecord, Hittable, Material, Point3, Ray, Vec3, AABB};
+use crate::{Float, HitRecord, Hittable, Material, Point3, Ray, Sampler, Vec3, AABB};
 use std::sync::Arc;
 
 macro_rules! makeRect {
@@ -81,14 +81,19 @@ macro_rules! makeRect {
                 distance_squared / (cosine * area)
             }
 
-            fn random(&self, o: &Point3) -> Vec3 {
+            fn random(&self, o: &Point3, sampler: &mut dyn Sampler) -> Vec3 {
+                let (s, t) = sampler.get_2d();
                 let random_point = $p(
-                    random_range(self.$a0..self.$a1),
-                    random_range(self.$b0..self.$b1),
+                    self.$a0 + s * (self.$a1 - self.$a0),
+                    self.$b0 + t * (self.$b1 - self.$b0),
                     self.k,
                 );
                 random_point - *o
             }
+
+            fn is_emissive(&self) -> bool {
+                self.material.is_emissive()
+            }
         }
     };
 }
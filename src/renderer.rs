@@ -0,0 +1,440 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    ray_color, tone_map::luminance, Camera, Color, Environment, Float, Hittable, HittableList,
+    MisHeuristic, RenderMode, Sampler, SamplerKind,
+};
+
+/// Settings of adaptive sampling. Every pixel gets `min_samples`, then
+/// pixels keep sampling in rounds until the standard error of their
+/// luminance falls below `noise_threshold` times their mean luminance, they
+/// reach `max_samples` or the budget of `samples_per_pixel` times the
+/// number of pixels is spent.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSettings {
+    pub noise_threshold: Float,
+    pub min_samples: u32,
+    pub max_samples: u32,
+}
+
+impl AdaptiveSettings {
+    /// Defaults for a budget of `samples_per_pixel` samples on average.
+    pub fn new(noise_threshold: Float, samples_per_pixel: u32) -> Self {
+        Self {
+            noise_threshold,
+            min_samples: (samples_per_pixel / 4).max(8),
+            max_samples: samples_per_pixel * 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub image_width: u32,
+    pub image_height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+    /// Bounces before Russian roulette may end a path.
+    pub min_bounces: i32,
+    /// How light sampling and material sampling are combined.
+    pub mis: MisHeuristic,
+    /// Whether paths carry RGB or sampled wavelengths.
+    pub mode: RenderMode,
+    pub n_threads: usize,
+    pub tile_size: u32,
+    pub sampler: SamplerKind,
+    /// Spend the samples where the image is noisy instead of evenly.
+    pub adaptive: Option<AdaptiveSettings>,
+    /// Seed of all random decisions; equal seeds give identical images.
+    pub seed: u64,
+}
+
+impl RenderSettings {
+    pub fn new(image_width: u32, image_height: u32) -> Self {
+        Self {
+            image_width,
+            image_height,
+            samples_per_pixel: 10,
+            max_depth: 50,
+            min_bounces: 5,
+            mis: MisHeuristic::Power,
+            mode: RenderMode::Rgb,
+            n_threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 16,
+            sampler: SamplerKind::Sobol,
+            adaptive: None,
+            seed: 0,
+        }
+    }
+
+    fn tiles_x(&self) -> u32 {
+        self.image_width.div_ceil(self.tile_size)
+    }
+
+    fn tiles_y(&self) -> u32 {
+        self.image_height.div_ceil(self.tile_size)
+    }
+
+    fn pixel_count(&self) -> usize {
+        self.image_width as usize * self.image_height as usize
+    }
+}
+
+/// The rendered radiance and how many samples each pixel took, both row by
+/// row from the top.
+pub struct RenderOutput {
+    pub image: Vec<Color>,
+    pub sample_counts: Vec<u32>,
+}
+
+/// A rectangle of pixels, in image rows counted from the top.
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Tile {
+    fn new(index: usize, settings: &RenderSettings) -> Self {
+        let tiles_x = settings.tiles_x() as usize;
+        let x0 = (index % tiles_x) as u32 * settings.tile_size;
+        let y0 = (index / tiles_x) as u32 * settings.tile_size;
+        Self {
+            x0,
+            y0,
+            x1: (x0 + settings.tile_size).min(settings.image_width),
+            y1: (y0 + settings.tile_size).min(settings.image_height),
+        }
+    }
+
+    fn pixels(&self, image_width: u32) -> impl Iterator<Item = (u32, u32, usize)> + '_ {
+        (self.y0..self.y1).flat_map(move |y| {
+            (self.x0..self.x1).map(move |x| (x, y, (y * image_width + x) as usize))
+        })
+    }
+}
+
+/// Sum of the samples of a pixel with a running (Welford) mean and variance
+/// of their luminance.
+#[derive(Debug, Clone, Copy, Default)]
+struct PixelStats {
+    sum: Color,
+    count: u32,
+    mean: Float,
+    m2: Float,
+}
+
+impl PixelStats {
+    /// Luminance below which pixels count as black, so that their relative
+    /// error does not keep them sampling forever.
+    const BLACK: Float = 1e-3;
+
+    fn add(&mut self, color: Color) {
+        self.sum += color;
+        self.count += 1;
+        let l = luminance(&color);
+        let delta = l - self.mean;
+        self.mean += delta / self.count as Float;
+        self.m2 += delta * (l - self.mean);
+    }
+
+    fn converged(&self, noise_threshold: Float) -> bool {
+        if self.count < 2 {
+            return false;
+        }
+        let variance = self.m2 / (self.count - 1) as Float;
+        let standard_error = (variance / self.count as Float).sqrt();
+        standard_error <= noise_threshold * self.mean.max(Self::BLACK)
+    }
+
+    fn color(&self) -> Color {
+        if self.count == 0 {
+            Color::default()
+        } else {
+            self.sum / self.count as Float
+        }
+    }
+}
+
+/// What every tile of a render needs.
+struct Job<'a, H> {
+    world: &'a H,
+    lights: &'a HittableList,
+    targets: &'a HittableList,
+    environment: &'a dyn Environment,
+    cam: &'a Camera,
+    settings: &'a RenderSettings,
+}
+
+impl<H: Hittable> Job<'_, H> {
+    /// Adds `samples[i]` samples to every pixel `i` of `tile`.
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        samples: &[u32],
+        stats: &mut [PixelStats],
+        sampler: &mut dyn Sampler,
+    ) {
+        let settings = self.settings;
+        let width = (settings.image_width - 1) as Float;
+        let height = (settings.image_height - 1) as Float;
+
+        for ((i, y, index), stats) in tile.pixels(settings.image_width).zip(stats) {
+            let j = settings.image_height - 1 - y;
+            for _ in 0..samples[index] {
+                sampler.start_pixel_sample(i as usize, y as usize, stats.count);
+                let (du, dv) = sampler.get_pixel_2d();
+                let u = (i as Float + du) / width;
+                let v = (j as Float + dv) / height;
+                let ray = self.cam.get_ray(u, v, sampler);
+                stats.add(ray_color(
+                    &ray,
+                    self.environment,
+                    self.world,
+                    self.lights,
+                    self.targets,
+                    settings,
+                    sampler,
+                ));
+            }
+        }
+    }
+
+    /// Runs one round of sampling on all threads, `samples[i]` more samples
+    /// for pixel `i`, counting them in `samples_done`.
+    fn round(
+        &self,
+        samples: &[u32],
+        stats: &Mutex<Vec<PixelStats>>,
+        samples_done: &AtomicUsize,
+        budget: usize,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) {
+        let settings = self.settings;
+        let n_tiles = (settings.tiles_x() * settings.tiles_y()) as usize;
+        let next_tile = AtomicUsize::new(0);
+        let max_samples = settings
+            .adaptive
+            .map_or(settings.samples_per_pixel, |a| a.max_samples);
+
+        thread::scope(|scope| {
+            for _ in 0..settings.n_threads.max(1) {
+                scope.spawn(|| {
+                    let mut sampler = settings.sampler.build(max_samples, settings.seed);
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if index >= n_tiles {
+                            break;
+                        }
+                        let tile = Tile::new(index, settings);
+                        let tile_samples: usize = tile
+                            .pixels(settings.image_width)
+                            .map(|(_, _, i)| samples[i] as usize)
+                            .sum();
+                        if tile_samples == 0 {
+                            continue;
+                        }
+
+                        let mut tile_stats: Vec<PixelStats> = {
+                            let stats = stats.lock().unwrap();
+                            tile.pixels(settings.image_width)
+                                .map(|(_, _, i)| stats[i])
+                                .collect()
+                        };
+                        self.render_tile(&tile, samples, &mut tile_stats, &mut *sampler);
+                        {
+                            let mut stats = stats.lock().unwrap();
+                            for ((_, _, i), s) in tile.pixels(settings.image_width).zip(tile_stats)
+                            {
+                                stats[i] = s;
+                            }
+                        }
+
+                        let done = samples_done.fetch_add(tile_samples, Ordering::Relaxed);
+                        progress((done + tile_samples).min(budget), budget);
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// How many more samples every pixel gets in the next adaptive round, all
+/// zero when the render is done. A few samples can miss everything that
+/// makes a pixel noisy, so pixels keep sampling while any pixel of their
+/// 3x3 neighbourhood has not converged.
+fn next_round(
+    adaptive: &AdaptiveSettings,
+    stats: &[PixelStats],
+    width: usize,
+    samples_left: usize,
+) -> Vec<u32> {
+    let height = stats.len() / width;
+    let noisy: Vec<bool> = stats
+        .iter()
+        .map(|s| !s.converged(adaptive.noise_threshold))
+        .collect();
+    let active: Vec<bool> = (0..stats.len())
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            stats[i].count < adaptive.max_samples
+                && (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                    (x.saturating_sub(1)..(x + 2).min(width)).any(|nx| noisy[ny * width + nx])
+                })
+        })
+        .collect();
+
+    let mut samples = vec![0; stats.len()];
+    let n_active = active.iter().filter(|&&a| a).count();
+    if n_active == 0 || samples_left == 0 {
+        return samples;
+    }
+    // share what is left, but in small enough batches to notice convergence
+    let batch = (samples_left / n_active).clamp(1, adaptive.min_samples as usize) as u32;
+    let mut left = samples_left;
+    for ((samples, s), _) in samples
+        .iter_mut()
+        .zip(stats)
+        .zip(&active)
+        .filter(|(_, &a)| a)
+    {
+        if left == 0 {
+            break;
+        }
+        *samples = batch.min(adaptive.max_samples - s.count).min(left as u32);
+        left -= *samples as usize;
+    }
+    samples
+}
+
+/// Renders the image on `settings.n_threads` scoped threads. The image is cut
+/// into square tiles which idle threads take from a shared counter, so fast
+/// and slow regions balance out. Every thread has its own `settings.sampler`,
+/// whose values depend only on `settings.seed`, the pixel, the sample index
+/// and the dimension, so the result does not depend on the number of
+/// threads. With `settings.adaptive` the tiles are rendered in rounds, each
+/// round only sampling the pixels that are still noisy.
+///
+/// `lights` are sampled by shadow rays and `targets` by the rays that
+/// continue the paths, see `ray_color`.
+///
+/// `progress` is called with the number of samples taken and the total
+/// budget after every tile; it is last called with both equal.
+pub fn render(
+    world: &impl Hittable,
+    lights: &HittableList,
+    targets: &HittableList,
+    environment: &dyn Environment,
+    cam: &Camera,
+    settings: &RenderSettings,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> RenderOutput {
+    let job = Job {
+        world,
+        lights,
+        targets,
+        environment,
+        cam,
+        settings,
+    };
+    let n_pixels = settings.pixel_count();
+    let budget = settings.samples_per_pixel as usize * n_pixels;
+    let stats = Mutex::new(vec![PixelStats::default(); n_pixels]);
+    let samples_done = AtomicUsize::new(0);
+
+    let first = settings
+        .adaptive
+        .map_or(settings.samples_per_pixel, |a| a.min_samples);
+    let mut samples = vec![first; n_pixels];
+    loop {
+        job.round(&samples, &stats, &samples_done, budget, progress);
+        let Some(adaptive) = &settings.adaptive else {
+            break;
+        };
+        let samples_left = budget.saturating_sub(samples_done.load(Ordering::Relaxed));
+        samples = next_round(
+            adaptive,
+            &stats.lock().unwrap(),
+            settings.image_width as usize,
+            samples_left,
+        );
+        if samples.iter().all(|&n| n == 0) {
+            break;
+        }
+    }
+    if samples_done.load(Ordering::Relaxed) < budget {
+        progress(budget, budget);
+    }
+
+    let stats = stats.into_inner().unwrap();
+    RenderOutput {
+        image: stats.iter().map(PixelStats::color).collect(),
+        sample_counts: stats.iter().map(|s| s.count).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::Scene;
+
+    const SCENE: &str = "
+image width=8 aspect=1 spp=4 depth=8
+camera lookfrom=0,0,6 lookat=0,0,0 vfov=40
+background color=0.2
+material white lambertian color=0.8
+material glass dielectric ir=1.5
+material fog isotropic color=0.9
+material light diffuse_light color=4
+sphere center=-1,0,0 radius=0.8 material=white
+sphere center=1,0,0 radius=0.8 material=glass
+sphere center=0,0,2 radius=1 material=fog density=0.8
+xz_rect x=-1,1 z=-1,1 k=2 material=light
+";
+
+    fn render_scene(sampler: SamplerKind, seed: u64, n_threads: usize) -> Vec<Color> {
+        let scene = Scene::parse(SCENE, Path::new(".")).unwrap();
+        let mut settings = RenderSettings::new(8, 8);
+        settings.samples_per_pixel = scene.samples_per_pixel;
+        settings.max_depth = scene.max_depth;
+        settings.tile_size = 4;
+        settings.sampler = sampler;
+        settings.seed = seed;
+        settings.n_threads = n_threads;
+        let output = render(
+            &scene.world,
+            &scene.light_list(),
+            &scene.lights,
+            &*scene.environment,
+            &scene.camera(),
+            &settings,
+            &|_, _| {},
+        );
+        output.image
+    }
+
+    #[test]
+    fn equal_seeds_render_equal_images() {
+        for sampler in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let first = render_scene(sampler, 7, 1);
+            assert_eq!(first, render_scene(sampler, 7, 1), "{sampler:?}");
+            assert_eq!(first, render_scene(sampler, 7, 3), "{sampler:?}");
+            assert_ne!(first, render_scene(sampler, 8, 1), "{sampler:?}");
+        }
+    }
+}
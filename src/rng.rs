@@ -0,0 +1,97 @@
+use std::ops::Range;
+
+use rand::{Error, RngCore};
+
+use crate::{Float, Ray};
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// Finalizer of SplitMix64, used to turn structured inputs such as pixel
+/// coordinates into well distributed seeds.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Small, fast and deterministic PCG32 generator. Every random decision of
+/// the renderer draws from one of these, so a render is reproducible from
+/// its seed regardless of thread count and scheduling.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    /// A generator for `seed`. Different `stream`s give independent
+    /// sequences for the same seed.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(mix(seed));
+        rng.next_u32();
+        rng
+    }
+
+    /// The generator for sample `sample` of pixel (`x`, `y`) of a render
+    /// seeded with `seed`.
+    pub fn for_sample(seed: u64, x: usize, y: usize, sample: u32) -> Self {
+        let pixel = ((y as u64) << 32) | x as u64;
+        Self::new(mix(seed) ^ pixel, sample as u64)
+    }
+
+    /// A generator derived from a ray, for the rare decisions made where no
+    /// generator is at hand, such as inside `Hittable::hit`. Equal rays with
+    /// equal seeds give equal sequences.
+    pub fn for_ray(ray: &Ray) -> Self {
+        let [ox, oy, oz]: [Float; 3] = ray.origin().into();
+        let [dx, dy, dz]: [Float; 3] = ray.direction().into();
+        let seed = [ox, oy, oz, dx, dy, dz, ray.time()]
+            .iter()
+            .fold(mix(ray.seed()), |h, f| mix(h ^ f.to_bits()));
+        Self::new(seed, 0)
+    }
+
+    /// A uniform number in [0, 1).
+    pub fn float(&mut self) -> Float {
+        (self.next_u64() >> 11) as Float * (1.0 / (1u64 << 53) as Float)
+    }
+
+    pub fn range(&mut self, range: Range<Float>) -> Float {
+        range.start + self.float() * (range.end - range.start)
+    }
+
+    /// A uniform index in `0..n`.
+    pub fn index(&mut self, n: usize) -> usize {
+        ((self.float() * n as Float) as usize).min(n - 1)
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
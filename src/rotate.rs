@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{Float, HitRecord, Hittable, Point3, Ray, AABB};
+use crate::{Color, Float, HitRecord, Hittable, HittableList, Point3, Ray, Sampler, Vec3, AABB};
 
 macro_rules! makeRotate {
     ($name: ident, $f: ident) => {
@@ -61,7 +61,7 @@ macro_rules! makeRotate {
                 let origin = ray.origin().$f(-self.theta);
                 let direction = ray.direction().$f(-self.theta);
 
-                let rotated_ray = Ray::new(origin, direction, ray.time());
+                let rotated_ray = ray.transformed(origin, direction);
                 if !self.inner.hit(&rotated_ray, t_min, t_max, rec) {
                     return false;
                 }
@@ -74,6 +74,40 @@ macro_rules! makeRotate {
 
                 true
             }
+
+            fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+                self.inner.pdf_value(&o.$f(-self.theta), &v.$f(-self.theta))
+            }
+
+            fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+                self.inner
+                    .random(&o.$f(-self.theta), sampler)
+                    .$f(self.theta)
+            }
+
+            fn is_emissive(&self) -> bool {
+                self.inner.is_emissive()
+            }
+
+            fn collect_lights(&self, lights: &mut HittableList) {
+                let mut inner_lights = HittableList::new();
+                self.inner.collect_lights(&mut inner_lights);
+                for light in inner_lights.iter() {
+                    lights.add($name::new(light.clone(), self.theta.to_degrees()));
+                }
+            }
+
+            fn has_media(&self) -> bool {
+                self.inner.has_media()
+            }
+
+            fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+                let rotated_ray = ray.transformed(
+                    ray.origin().$f(-self.theta),
+                    ray.direction().$f(-self.theta),
+                );
+                self.inner.transmittance(&rotated_ray, t_min, t_max)
+            }
         }
     };
 }
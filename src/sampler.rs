@@ -0,0 +1,471 @@
+use crate::{rng::mix, Float, Rng};
+
+/// Largest float below one, samples are clamped to it.
+const ONE_MINUS_EPSILON: Float = 1.0 - Float::EPSILON / 2.0;
+
+/// Dimensions used by the camera: pixel position, lens position and time,
+/// the wavelength in spectral mode and the seed of the camera ray.
+const CAMERA_DIMENSIONS: u32 = 7;
+/// Dimensions reserved for each bounce, so that the same decision of the
+/// same bounce uses the same dimension in every sample, however many
+/// values earlier bounces took.
+const BOUNCE_DIMENSIONS: u32 = 12;
+
+/// Source of the sample values of one pixel sample. Every call consumes the
+/// next dimension (two for `get_2d`), so a sample index and a dimension
+/// identify a value and successive samples of a pixel are well distributed
+/// in every dimension.
+pub trait Sampler {
+    /// Starts sample `index` of pixel (`x`, `y`) at dimension zero.
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32);
+
+    /// Moves on to the dimensions of the next bounce of the path.
+    fn start_bounce(&mut self);
+
+    fn get_1d(&mut self) -> Float;
+
+    fn get_2d(&mut self) -> (Float, Float);
+
+    /// The position of the sample inside the pixel.
+    fn get_pixel_2d(&mut self) -> (Float, Float) {
+        self.get_2d()
+    }
+}
+
+/// The sampler implementations `render` can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(Self::Independent),
+            "stratified" => Some(Self::Stratified),
+            "halton" => Some(Self::Halton),
+            "sobol" => Some(Self::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn build(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        let state = SampleState::new(samples_per_pixel, seed);
+        match self {
+            Self::Independent => Box::new(IndependentSampler {
+                state,
+                rng: Rng::new(seed, 0),
+            }),
+            Self::Stratified => Box::new(StratifiedSampler { state }),
+            Self::Halton => Box::new(HaltonSampler { state }),
+            Self::Sobol => Box::new(SobolSampler { state }),
+        }
+    }
+}
+
+/// The current pixel sample and dimension, shared by the samplers.
+struct SampleState {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel: (usize, usize),
+    index: u32,
+    dimension: u32,
+    bounce: u32,
+}
+
+impl SampleState {
+    fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            seed,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            bounce: 0,
+        }
+    }
+
+    fn start(&mut self, x: usize, y: usize, index: u32) {
+        self.pixel = (x, y);
+        self.index = index;
+        self.dimension = 0;
+        self.bounce = 0;
+    }
+
+    fn start_bounce(&mut self) {
+        self.dimension = CAMERA_DIMENSIONS + self.bounce * BOUNCE_DIMENSIONS;
+        self.bounce += 1;
+    }
+
+    /// A hash of the pixel and the next `count` dimensions, which then
+    /// count as consumed.
+    fn next_hash(&mut self, count: u32) -> u64 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        [self.pixel.0 as u64, self.pixel.1 as u64, dimension as u64]
+            .iter()
+            .fold(mix(self.seed), |h, v| mix(h ^ v))
+    }
+}
+
+fn to_float(bits: u32) -> Float {
+    (bits as Float / (1u64 << 32) as Float).min(ONE_MINUS_EPSILON)
+}
+
+/// Element `i` of a pseudo random permutation of `0..n` selected by
+/// `seed`, after Kensler, "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let p = seed;
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % n
+}
+
+/// Uniform random samples, each pixel sample with its own generator.
+pub struct IndependentSampler {
+    state: SampleState,
+    rng: Rng,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+        self.rng = Rng::for_sample(self.state.seed, x, y, index);
+    }
+
+    fn start_bounce(&mut self) {}
+
+    fn get_1d(&mut self) -> Float {
+        self.rng.float()
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        (self.rng.float(), self.rng.float())
+    }
+}
+
+/// Jittered stratification: the samples of a pixel fall into distinct
+/// strata of every dimension, visited in a different random order per
+/// dimension so that dimensions stay uncorrelated.
+pub struct StratifiedSampler {
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    fn jitter(&self, hash: u64) -> Rng {
+        Rng::new(hash, self.state.index as u64)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn start_bounce(&mut self) {
+        self.state.start_bounce();
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let hash = self.state.next_hash(1);
+        let n = self.state.samples_per_pixel;
+        let stratum = permutation_element(self.state.index, n, hash as u32);
+        ((stratum as Float + self.jitter(hash).float()) / n as Float).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let hash = self.state.next_hash(2);
+        let n = self.state.samples_per_pixel;
+        // the smallest grid with at least one stratum per sample
+        let nx = (n as Float).sqrt().ceil() as u32;
+        let ny = n.div_ceil(nx);
+        let stratum = permutation_element(self.state.index % (nx * ny), nx * ny, hash as u32);
+        let mut jitter = self.jitter(hash);
+        let x = (stratum % nx) as Float + jitter.float();
+        let y = (stratum / nx) as Float + jitter.float();
+        (
+            (x / nx as Float).min(ONE_MINUS_EPSILON),
+            (y / ny as Float).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+const HALTON_DIMENSIONS: usize = 64;
+
+const fn primes<const N: usize>() -> [u32; N] {
+    let mut primes = [0; N];
+    let mut count = 0;
+    let mut candidate = 2;
+    while count < N {
+        let mut i = 0;
+        let mut is_prime = true;
+        while i < count {
+            if candidate % primes[i] == 0 {
+                is_prime = false;
+                break;
+            }
+            i += 1;
+        }
+        if is_prime {
+            primes[count] = candidate;
+            count += 1;
+        }
+        candidate += 1;
+    }
+    primes
+}
+
+const PRIMES: [u32; HALTON_DIMENSIONS] = primes();
+
+/// Radical inverse of `a` in `base` with the digits scrambled by a random
+/// permutation per digit and prefix, i.e. a nested uniform (Owen) scramble.
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, hash: u64) -> Float {
+    let base = base as u64;
+    let inv_base = 1.0 / base as Float;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits = 0u64;
+    // keep going past the digits of `a`, its zero digits get scrambled too,
+    // as long as the next digit still shows in the result
+    while 1.0 - (base - 1) as Float * inv_base_m * inv_base < 1.0 {
+        let next = a / base;
+        let digit = (a - next * base) as u32;
+        let digit_hash = mix(hash ^ reversed_digits) as u32;
+        let digit = permutation_element(digit, base as u32, digit_hash);
+        reversed_digits = reversed_digits * base + digit as u64;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (inv_base_m * reversed_digits as Float).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, dimension `i` using the `i`th prime as base, Owen
+/// scrambled per pixel. Dimensions past the prime table get uniform random
+/// values.
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    fn sample(&self, dimension: u32, hash: u64) -> Float {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => owen_scrambled_radical_inverse(base, self.state.index as u64, hash),
+            None => Rng::new(hash, self.state.index as u64).float(),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn start_bounce(&mut self) {
+        self.state.start_bounce();
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let dimension = self.state.dimension;
+        let hash = self.state.next_hash(1);
+        self.sample(dimension, hash)
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let dimension = self.state.dimension;
+        let hash = self.state.next_hash(2);
+        (
+            self.sample(dimension, hash),
+            self.sample(dimension + 1, mix(hash)),
+        )
+    }
+}
+
+/// Second dimension of the Sobol sequence, after Kollig and Keller,
+/// "Efficient Multidimensional Sampling".
+fn sobol_2(mut i: u32) -> u32 {
+    let mut r = 0;
+    let mut v = 1 << 31;
+    while i != 0 {
+        if i & 1 != 0 {
+            r ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    r
+}
+
+/// Hash based approximation of a nested uniform scramble of the bits of
+/// `v`, after Laine and Karras.
+fn fast_owen_scramble(mut v: u32, seed: u32) -> u32 {
+    v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+/// Owen scrambled Sobol (0,2) sequence, padded to any number of
+/// dimensions: every 1D or 2D request uses the first one or two Sobol
+/// dimensions with its own scramble and its own shuffle of the sample
+/// index. The shuffle is itself an Owen scramble, which keeps every power
+/// of two prefix of a pixel's samples well stratified (Burley, "Practical
+/// Hash-based Owen Scrambling"), so any number of samples may be taken.
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    fn index(&self, hash: u64) -> u32 {
+        fast_owen_scramble(self.state.index, hash as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: usize, y: usize, index: u32) {
+        self.state.start(x, y, index);
+    }
+
+    fn start_bounce(&mut self) {
+        self.state.start_bounce();
+    }
+
+    fn get_1d(&mut self) -> Float {
+        let hash = self.state.next_hash(1);
+        let index = self.index(hash);
+        to_float(fast_owen_scramble(
+            index.reverse_bits(),
+            (hash >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> (Float, Float) {
+        let hash = self.state.next_hash(2);
+        let index = self.index(hash);
+        let seed = mix(hash);
+        (
+            to_float(fast_owen_scramble(index.reverse_bits(), seed as u32)),
+            to_float(fast_owen_scramble(sobol_2(index), (seed >> 32) as u32)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    /// The first value of every bounce of sample `index` of a pixel, for
+    /// more bounces than a path usually takes.
+    fn values(sampler: &mut dyn Sampler, index: u32) -> Vec<Float> {
+        sampler.start_pixel_sample(3, 5, index);
+        let mut values = vec![sampler.get_1d()];
+        for _ in 0..64 {
+            sampler.start_bounce();
+            let (u, v) = sampler.get_2d();
+            values.extend([sampler.get_1d(), u, v]);
+        }
+        values
+    }
+
+    #[test]
+    fn samples_are_in_unit_interval_and_repeatable() {
+        for kind in KINDS {
+            let mut first = kind.build(16, 1);
+            let mut second = kind.build(16, 1);
+            for index in 0..16 {
+                let values = values(&mut *first, index);
+                assert!(values.iter().all(|v| (0.0..1.0).contains(v)), "{kind:?}");
+                assert_eq!(values, self::values(&mut *second, index), "{kind:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_change_samples() {
+        for kind in KINDS {
+            let a = values(&mut *kind.build(16, 1), 0);
+            let b = values(&mut *kind.build(16, 2), 0);
+            assert_ne!(a, b, "{kind:?}");
+        }
+    }
+
+    /// Whether the first `n` samples of a pixel fall into the `n` strata of
+    /// dimension `dimension` of the bounce `bounce`.
+    fn stratified(kind: SamplerKind, n: u32, bounce: u32, dimension: u32) -> bool {
+        let mut sampler = kind.build(n, 1);
+        let mut strata = vec![false; n as usize];
+        for index in 0..n {
+            sampler.start_pixel_sample(0, 0, index);
+            for _ in 0..bounce {
+                sampler.start_bounce();
+            }
+            for _ in 0..dimension {
+                sampler.get_1d();
+            }
+            strata[(sampler.get_1d() * n as Float) as usize] = true;
+        }
+        strata.iter().all(|&s| s)
+    }
+
+    #[test]
+    fn samples_of_a_pixel_are_stratified() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            for (bounce, dimension) in [(0, 0), (0, 5), (1, 0), (3, 2)] {
+                assert!(stratified(kind, 16, bounce, dimension), "{kind:?}");
+            }
+        }
+        // Halton stratifies dimension `i` in powers of the `i`th prime
+        assert!(stratified(SamplerKind::Halton, 16, 0, 0));
+        assert!(stratified(SamplerKind::Halton, 9, 0, 1));
+        assert!(stratified(SamplerKind::Halton, 25, 0, 2));
+    }
+
+    #[test]
+    fn permutation_element_permutes() {
+        for n in [1, 2, 7, 16, 41] {
+            let mut seen = vec![false; n as usize];
+            for i in 0..n {
+                seen[permutation_element(i, n, 0x1234_5678) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s), "{n}");
+        }
+    }
+}
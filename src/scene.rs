@@ -0,0 +1,1260 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{self, Display},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use crate::{
+    load_obj, load_voxel_grid, read_hdr, read_raw_grid, BlockBox, BvhNode, Camera, CameraConfig,
+    CheckerTexture, CoatedMaterial, Color, Conductor, ConstantEnvironment, ConstantMedium,
+    Dielectric, DiffuseLight, Dispersion, EmptyMaterial, Environment, EnvironmentMap, Falloff,
+    FlipFace, Float, GradientSky, GridMedium, HenyeyGreenstein, HeterogeneousMedium, Hittable,
+    HittableList, ImageTexture, Interior, Isotropic, Lambertian, Material, Matrix4, Metal,
+    MixMaterial, MovingSphere, NoiseDensity, NoiseTexture, Principled, RoughDielectric, SolidColor,
+    Sphere, SunSky, Texture, Transform, Triangle, TriangleMesh, Vec3, XyRect, XzRect, YzRect, AABB,
+    PI,
+};
+
+/// Everything needed to render an image: geometry, importance sampling
+/// targets, camera and output settings.
+pub struct Scene {
+    pub world: HittableList,
+    /// Objects, such as glass spheres, that scattered rays are sent towards
+    /// as often as the materials send them, to find the light behind them.
+    /// Emissive objects of `world` need not be listed: shadow rays sample
+    /// them anyway.
+    pub lights: HittableList,
+    pub camera: CameraConfig,
+    /// What rays leaving the scene see.
+    pub environment: Arc<dyn Environment>,
+    pub aspect_ratio: Float,
+    pub image_width: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: i32,
+}
+
+impl Scene {
+    pub fn new(world: HittableList) -> Self {
+        Self {
+            world,
+            lights: HittableList::new(),
+            camera: CameraConfig::default(),
+            environment: Arc::new(ConstantEnvironment::new(Color::new(0.7, 0.8, 1.0))),
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 10,
+            max_depth: 50,
+        }
+    }
+
+    pub fn image_height(&self) -> u32 {
+        (self.image_width as Float / self.aspect_ratio) as u32
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.camera.build(self.aspect_ratio)
+    }
+
+    /// The objects to importance sample: every emissive object of the world.
+    pub fn light_list(&self) -> HittableList {
+        self.world.lights()
+    }
+
+    /// Loads a scene description file. Relative paths inside the file (e.g.
+    /// image textures) are resolved against the directory of the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| SceneError::new(0, format!("cannot read {}: {}", path.display(), e)))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::parse(&source, base_dir)
+    }
+
+    /// Parses a scene description.
+    ///
+    /// The format is line based. `#` at the start of a line or after
+    /// whitespace starts a comment, so paths may contain it. Every other
+    /// non-empty line is a statement made of a keyword followed by
+    /// `key=value` attributes and bare flags. Vectors and colors are
+    /// written as `x,y,z`, ranges as `a,b`.
+    ///
+    /// ```text
+    /// image width=400 aspect=1 spp=100 depth=50
+    /// camera lookfrom=278,278,-800 lookat=278,278,0 vfov=40 aperture=0
+    /// background color=0,0,0
+    ///
+    /// texture checker checker even=0.2,0.3,0.1 odd=0.9,0.9,0.9
+    /// material white lambertian color=0.73
+    /// material light diffuse_light color=15
+    ///
+    /// xz_rect x=213,343 z=227,332 k=554 material=light flip
+    /// box min=0,0,0 max=165,330,165 material=white rotate_y=15 translate=265,0,295
+    ///
+    /// group
+    /// sphere center=0,0,0 radius=10 material=white
+    /// sphere center=20,0,0 radius=10 material=white
+    /// end bvh translate=-100,270,395
+    ///
+    /// group
+    /// mesh file=teapot.obj material=white
+    /// end bvh name=teapot
+    /// instance teapot scale=10 translate=100,0,100
+    /// instance teapot rotate=1,1,0,45 translate=300,0,100
+    /// ```
+    ///
+    /// Statements:
+    ///
+    /// * `image width= aspect= spp= depth=`
+    /// * `camera lookfrom= lookat= vup= vfov= aperture= focus_dist= time=`
+    /// * `background color=`, a constant environment
+    /// * `environment constant|gradient|sun_sky|map ...`, see below
+    /// * `texture NAME solid|checker|noise|image ...`
+    /// * `material NAME lambertian|metal|dielectric|diffuse_light|isotropic ...`
+    /// * `material NAME conductor preset=gold|copper|aluminium roughness=`, or
+    ///   with `eta=` and `k=` instead of a preset
+    /// * `material NAME dielectric ir=`, or dispersive with `preset=bk7|diamond`,
+    ///   `cauchy=a,b` or `sellmeier=b1,b2,b3,c1,c2,c3` (wavelengths in
+    ///   micrometers) instead of `ir=`, seen in spectral mode
+    /// * `material NAME rough_dielectric ir= roughness=`
+    /// * both dielectrics optionally with `absorption=` per unit of length,
+    ///   or the `tint=` color light takes on after `tint_distance=`, and a
+    ///   `priority=` for nesting, e.g. a liquid of lower priority slightly
+    ///   overlapping the inner wall of its glass
+    /// * `material NAME principled base_color= metallic= roughness= specular=
+    ///   specular_tint= sheen= clearcoat= transmission= emission= ior=`, all
+    ///   optional and each a texture name or a value, and the interior of
+    ///   its glass like the dielectrics
+    /// * `material NAME diffuse_light color=|texture=`, or `power=` in watts,
+    ///   emitting from the front face unless flagged `two_sided`, optionally
+    ///   with `spot=inner,outer` (degrees) or `profile=` values at even
+    ///   angles from 0 to 90 degrees. Each shape using a light in watts
+    ///   emits that power over its own area, after a uniform `scale=`.
+    ///   Meshes, shapes in groups, unevenly scaled shapes and other
+    ///   materials can only use it with `area=` on the light, one area
+    ///   that all of them then share
+    /// * `material NAME mix first= second= weight=`, `second` where the
+    ///   weight texture or value is high
+    /// * `material NAME coated base= ir= roughness=`, a dielectric layer
+    ///   over material `base`
+    /// * `sphere`, `moving_sphere`, `xy_rect`, `xz_rect`, `yz_rect`, `box`,
+    ///   `triangle p0= p1= p2=`
+    /// * `mesh file=`, a Wavefront OBJ file whose faces without an MTL
+    ///   material use `material=` (default: gray lambertian)
+    /// * `volume file= sigma_a= sigma_s= anisotropy=`, a medium with the
+    ///   density of a voxel grid from an NRRD (`.nrrd`, `.nhdr`) or sparse
+    ///   block (`.vxb`) file, or from raw 32-bit floats given `dims=x,y,z`
+    ///   and optionally the grid's bounds `min=` and `max=`
+    /// * `group` ... `end`, collecting objects into one (optionally `bvh`);
+    ///   with `name=` the group is only defined, to be placed by
+    /// * `instance NAME`, sharing the geometry of a named group
+    ///
+    /// Objects, `end` and `instance` accept, composed in the order written,
+    /// `rotate_x=`, `rotate_y=`, `rotate_z=` (degrees), `rotate=x,y,z,degrees`
+    /// about an arbitrary axis, `scale=` (one factor or `x,y,z`) and
+    /// `translate=`, then `flip` to
+    /// flip faces and `density=` with `phase=` to turn the shape into the
+    /// boundary of a constant medium, or `noise=` (scale) with
+    /// `noise_depth=`, `sigma_a=` and `sigma_s=` (per channel, by default
+    /// none and one) into that of a medium with Perlin turbulence as its
+    /// density. Media scatter by the Henyey-Greenstein phase function with
+    /// asymmetry `anisotropy=`, from -1 (back) to 1 (forward), and
+    /// isotropically without it. Emissive objects are importance sampled
+    /// automatically; the `light` flag makes a top level object, e.g. a
+    /// glass sphere, a target that scattered rays are also sent towards.
+    ///
+    /// Environments:
+    ///
+    /// * `constant color=`
+    /// * `gradient bottom= top=`, blending from straight down to straight
+    ///   up (default: the white to blue sky of the first book)
+    /// * `sun_sky sun=x,y,z turbidity= intensity=`, a Preetham daylight sky
+    ///   with the sun in direction `sun` (default turbidity 3)
+    /// * `map file= rotate= intensity=`, an equirectangular Radiance `.hdr`
+    ///   image rotated by `rotate` degrees about +y
+    ///
+    /// Emissive environments are importance sampled like lights.
+    pub fn parse(source: &str, base_dir: &Path) -> Result<Self> {
+        let mut parser = Parser {
+            base_dir: base_dir.to_path_buf(),
+            scene: Scene::new(HittableList::new()),
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            power_lights: HashMap::new(),
+            groups: Vec::new(),
+            definitions: HashMap::new(),
+        };
+
+        for (index, line) in source.lines().enumerate() {
+            let line_no = index + 1;
+            let line = strip_comment(line);
+            let mut tokens = line.split_whitespace();
+            if let Some(keyword) = tokens.next() {
+                parser.statement(keyword, tokens.collect(), line_no)?;
+            }
+        }
+
+        if !parser.groups.is_empty() {
+            return Err(SceneError::new(
+                source.lines().count(),
+                "unterminated group, missing `end`",
+            ));
+        }
+        Ok(parser.scene)
+    }
+}
+
+/// `line` up to a comment, which starts with a `#` at the start of the
+/// line or after whitespace; a `#` inside a word such as a path is kept.
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let start = line.char_indices().find(|&(i, c)| {
+        c == '#'
+            && line[..i]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+    });
+    match start {
+        Some((i, _)) => &line[..i],
+        None => line,
+    }
+}
+
+#[derive(Debug)]
+pub struct SceneError {
+    line: usize,
+    message: String,
+}
+
+impl SceneError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+struct Attributes<'s> {
+    line: usize,
+    pairs: Vec<(&'s str, &'s str)>,
+    flags: Vec<&'s str>,
+    used: Vec<bool>,
+    used_flags: Vec<bool>,
+}
+
+impl<'s> Attributes<'s> {
+    fn new(tokens: &[&'s str], line: usize) -> Self {
+        let mut pairs = Vec::new();
+        let mut flags = Vec::new();
+        for token in tokens {
+            match token.split_once('=') {
+                Some(pair) => pairs.push(pair),
+                None => flags.push(*token),
+            }
+        }
+        Self {
+            line,
+            used: vec![false; pairs.len()],
+            used_flags: vec![false; flags.len()],
+            pairs,
+            flags,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> SceneError {
+        SceneError::new(self.line, message)
+    }
+
+    fn get(&mut self, key: &str) -> Option<&'s str> {
+        let index = self.pairs.iter().position(|(k, _)| *k == key)?;
+        self.used[index] = true;
+        Some(self.pairs[index].1)
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        match self.flags.iter().position(|f| *f == name) {
+            Some(index) => {
+                self.used_flags[index] = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'s str> {
+        self.get(key)
+            .ok_or_else(|| self.error(format!("missing attribute `{}`", key)))
+    }
+
+    fn parse_value<T: FromStr>(&self, key: &str, value: &str) -> Result<T> {
+        value
+            .parse()
+            .map_err(|_| self.error(format!("invalid value `{}` for `{}`", value, key)))
+    }
+
+    fn floats(&self, key: &str, value: &str) -> Result<Vec<Float>> {
+        value
+            .split(',')
+            .map(|part| self.parse_value(key, part))
+            .collect()
+    }
+
+    fn float(&mut self, key: &str) -> Result<Float> {
+        let value = self.required(key)?;
+        self.parse_value(key, value)
+    }
+
+    fn float_or(&mut self, key: &str, default: Float) -> Result<Float> {
+        match self.get(key) {
+            Some(value) => self.parse_value(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn uint_or(&mut self, key: &str, default: u32) -> Result<u32> {
+        match self.get(key) {
+            Some(value) => self.parse_value(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn range(&mut self, key: &str) -> Result<(Float, Float)> {
+        let value = self.required(key)?;
+        match self.floats(key, value)?[..] {
+            [a, b] => Ok((a, b)),
+            _ => Err(self.error(format!("`{}` expects two numbers `a,b`", key))),
+        }
+    }
+
+    fn vec3_value(&self, key: &str, value: &str) -> Result<Vec3> {
+        match self.floats(key, value)?[..] {
+            [x, y, z] => Ok(Vec3::new(x, y, z)),
+            _ => Err(self.error(format!("`{}` expects three numbers `x,y,z`", key))),
+        }
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Vec3> {
+        let value = self.required(key)?;
+        self.vec3_value(key, value)
+    }
+
+    fn vec3_or(&mut self, key: &str, default: Vec3) -> Result<Vec3> {
+        match self.get(key) {
+            Some(value) => self.vec3_value(key, value),
+            None => Ok(default),
+        }
+    }
+
+    /// Colors may also be given as a single gray value.
+    fn color_value(&self, key: &str, value: &str) -> Result<Color> {
+        match self.floats(key, value)?[..] {
+            [g] => Ok(Color::new(g, g, g)),
+            [r, g, b] => Ok(Color::new(r, g, b)),
+            _ => Err(self.error(format!("`{}` expects a color `r,g,b`", key))),
+        }
+    }
+
+    fn color(&mut self, key: &str) -> Result<Color> {
+        let value = self.required(key)?;
+        self.color_value(key, value)
+    }
+
+    fn color_or(&mut self, key: &str, default: Color) -> Result<Color> {
+        match self.get(key) {
+            Some(value) => self.color_value(key, value),
+            None => Ok(default),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        if let Some(index) = self.used.iter().position(|used| !used) {
+            return Err(self.error(format!("unknown attribute `{}`", self.pairs[index].0)));
+        }
+        if let Some(index) = self.used_flags.iter().position(|used| !used) {
+            return Err(self.error(format!("unknown flag `{}`", self.flags[index])));
+        }
+        Ok(())
+    }
+}
+
+const SHAPES: [&str; 7] = [
+    "sphere",
+    "moving_sphere",
+    "xy_rect",
+    "xz_rect",
+    "yz_rect",
+    "box",
+    "triangle",
+];
+
+struct Parser {
+    base_dir: PathBuf,
+    scene: Scene,
+    textures: HashMap<String, Arc<dyn Texture>>,
+    materials: HashMap<String, Arc<dyn Material>>,
+    /// Lights given in watts without an area, which take the area of each
+    /// shape using them.
+    power_lights: HashMap<String, DiffuseLight>,
+    groups: Vec<HittableList>,
+    definitions: HashMap<String, Arc<dyn Hittable>>,
+}
+
+impl Parser {
+    fn statement(&mut self, keyword: &str, tokens: Vec<&str>, line: usize) -> Result<()> {
+        match keyword {
+            "environment" => {
+                let Some((kind, tokens)) = tokens.split_first().filter(|(k, _)| !k.contains('='))
+                else {
+                    return Err(SceneError::new(line, "expected `environment KIND ...`"));
+                };
+                let mut attrs = Attributes::new(tokens, line);
+                self.scene.environment = self.environment(kind, &mut attrs)?;
+                attrs.finish()?;
+            }
+            "texture" | "material" => {
+                let (name, kind) = match tokens[..] {
+                    [name, kind, ..] if !name.contains('=') && !kind.contains('=') => (name, kind),
+                    _ => {
+                        return Err(SceneError::new(
+                            line,
+                            format!("expected `{} NAME KIND ...`", keyword),
+                        ))
+                    }
+                };
+                let mut attrs = Attributes::new(&tokens[2..], line);
+                if keyword == "texture" {
+                    let texture = self.texture(kind, &mut attrs)?;
+                    attrs.finish()?;
+                    self.textures.insert(name.to_owned(), texture);
+                } else if kind == "diffuse_light" {
+                    let light = self.diffuse_light(&mut attrs)?;
+                    let in_watts = attrs.get("power").is_some();
+                    let area = match in_watts.then(|| attrs.get("area")).flatten() {
+                        Some(area) => Some(attrs.parse_value("area", area)?),
+                        None => None,
+                    };
+                    attrs.finish()?;
+                    self.materials.remove(name);
+                    self.power_lights.remove(name);
+                    match area {
+                        Some(area) => {
+                            self.materials
+                                .insert(name.to_owned(), Arc::new(light.with_power(area)));
+                        }
+                        None if in_watts => {
+                            self.power_lights.insert(name.to_owned(), light);
+                        }
+                        None => {
+                            self.materials.insert(name.to_owned(), Arc::new(light));
+                        }
+                    }
+                } else {
+                    let material = self.material(kind, &mut attrs)?;
+                    attrs.finish()?;
+                    self.power_lights.remove(name);
+                    self.materials.insert(name.to_owned(), material);
+                }
+            }
+            "group" => {
+                Attributes::new(&tokens, line).finish()?;
+                self.groups.push(HittableList::new());
+            }
+            "end" => {
+                let group = self
+                    .groups
+                    .pop()
+                    .ok_or_else(|| SceneError::new(line, "`end` without `group`"))?;
+                if group.is_empty() {
+                    return Err(SceneError::new(line, "empty group"));
+                }
+                let mut attrs = Attributes::new(&tokens, line);
+                let object: Arc<dyn Hittable> = if attrs.flag("bvh") {
+                    let camera = &self.scene.camera;
+                    Arc::new(BvhNode::new(&group, camera.time0, camera.time1))
+                } else {
+                    Arc::new(group)
+                };
+                match attrs.get("name") {
+                    Some(name) => {
+                        attrs.finish()?;
+                        self.definitions.insert(name.to_owned(), object);
+                    }
+                    None => self.object(object, attrs)?,
+                }
+            }
+            "instance" => {
+                let Some((name, tokens)) = tokens.split_first().filter(|(n, _)| !n.contains('='))
+                else {
+                    return Err(SceneError::new(line, "expected `instance NAME ...`"));
+                };
+                let object =
+                    self.definitions.get(*name).cloned().ok_or_else(|| {
+                        SceneError::new(line, format!("unknown group `{}`", name))
+                    })?;
+                self.object(object, Attributes::new(tokens, line))?;
+            }
+            _ => {
+                let mut attrs = Attributes::new(&tokens, line);
+                match keyword {
+                    "image" => self.image(&mut attrs)?,
+                    "camera" => self.camera(&mut attrs)?,
+                    "background" => {
+                        self.scene.environment =
+                            Arc::new(ConstantEnvironment::new(attrs.color("color")?))
+                    }
+                    _ => {
+                        let shape = self.shape(keyword, &mut attrs)?;
+                        return self.object(shape, attrs);
+                    }
+                }
+                attrs.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn image(&mut self, attrs: &mut Attributes) -> Result<()> {
+        let scene = &mut self.scene;
+        scene.image_width = attrs.uint_or("width", scene.image_width)?;
+        if let Some(aspect) = attrs.get("aspect") {
+            scene.aspect_ratio = match aspect.split_once('/') {
+                Some((w, h)) => {
+                    attrs.parse_value::<Float>("aspect", w)?
+                        / attrs.parse_value::<Float>("aspect", h)?
+                }
+                None => attrs.parse_value("aspect", aspect)?,
+            };
+        }
+        scene.samples_per_pixel = attrs.uint_or("spp", scene.samples_per_pixel)?;
+        if let Some(depth) = attrs.get("depth") {
+            scene.max_depth = match attrs.parse_value("depth", depth)? {
+                depth if depth > 0 => depth,
+                _ => return Err(attrs.error("`depth` must be positive")),
+            };
+        }
+        if scene.image_width < 2 || scene.image_height() < 2 || scene.samples_per_pixel == 0 {
+            return Err(attrs.error("image must be at least 2x2 pixels with 1 sample"));
+        }
+        Ok(())
+    }
+
+    fn camera(&mut self, attrs: &mut Attributes) -> Result<()> {
+        let camera = &mut self.scene.camera;
+        camera.lookfrom = attrs.vec3_or("lookfrom", camera.lookfrom)?;
+        camera.lookat = attrs.vec3_or("lookat", camera.lookat)?;
+        camera.vup = attrs.vec3_or("vup", camera.vup)?;
+        camera.vfov = attrs.float_or("vfov", camera.vfov)?;
+        camera.aperture = attrs.float_or("aperture", camera.aperture)?;
+        camera.focus_dist = attrs.float_or("focus_dist", camera.focus_dist)?;
+        if attrs.get("time").is_some() {
+            (camera.time0, camera.time1) = attrs.range("time")?;
+        }
+        Ok(())
+    }
+
+    fn environment(&mut self, kind: &str, attrs: &mut Attributes) -> Result<Arc<dyn Environment>> {
+        Ok(match kind {
+            "constant" => Arc::new(ConstantEnvironment::new(attrs.color("color")?)),
+            "gradient" => {
+                let default = GradientSky::default();
+                match (attrs.get("bottom"), attrs.get("top")) {
+                    (None, None) => Arc::new(default),
+                    (Some(bottom), Some(top)) => Arc::new(GradientSky::new(
+                        attrs.color_value("bottom", bottom)?,
+                        attrs.color_value("top", top)?,
+                    )),
+                    _ => return Err(attrs.error("gradient needs both `bottom` and `top`")),
+                }
+            }
+            "sun_sky" => {
+                let sun = attrs.vec3("sun")?;
+                if sun.near_zero() {
+                    return Err(attrs.error("`sun` must be a direction"));
+                }
+                Arc::new(SunSky::new(
+                    sun,
+                    attrs.float_or("turbidity", 3.0)?,
+                    attrs.float_or("intensity", 1.0)?,
+                ))
+            }
+            "map" => {
+                let path = self.base_dir.join(attrs.required("file")?);
+                let (mut pixels, width, _) = File::open(&path)
+                    .and_then(|file| read_hdr(BufReader::new(file)))
+                    .map_err(|e| attrs.error(format!("cannot load {}: {}", path.display(), e)))?;
+                let intensity = attrs.float_or("intensity", 1.0)?;
+                for pixel in &mut pixels {
+                    *pixel *= intensity;
+                }
+                Arc::new(EnvironmentMap::new(
+                    pixels,
+                    width,
+                    attrs.float_or("rotate", 0.0)?,
+                ))
+            }
+            _ => return Err(attrs.error(format!("unknown environment kind `{}`", kind))),
+        })
+    }
+
+    /// A texture reference is either the name of a texture or a color.
+    fn texture_ref(
+        &mut self,
+        attrs: &mut Attributes,
+        key: &str,
+    ) -> Result<Option<Arc<dyn Texture>>> {
+        match attrs.get(key) {
+            None => Ok(None),
+            Some(value) => match self.textures.get(value) {
+                Some(texture) => Ok(Some(texture.clone())),
+                None if value.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '-') => {
+                    Ok(Some(Arc::new(SolidColor::from(
+                        attrs.color_value(key, value)?,
+                    ))))
+                }
+                None => Err(attrs.error(format!("unknown texture `{}`", value))),
+            },
+        }
+    }
+
+    /// Looks up the material named by attribute `key`.
+    fn material_ref(&self, attrs: &mut Attributes, key: &str) -> Result<Arc<dyn Material>> {
+        let name = attrs.required(key)?;
+        if self.power_lights.contains_key(name) {
+            return Err(attrs.error(format!(
+                "light `{}` is given in watts without `area=`, only shapes can use it",
+                name
+            )));
+        }
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| attrs.error(format!("unknown material `{}`", name)))
+    }
+
+    /// Reads the `texture=` or `color=` attribute of a material.
+    fn albedo(&mut self, attrs: &mut Attributes) -> Result<Arc<dyn Texture>> {
+        if let Some(texture) = self.texture_ref(attrs, "texture")? {
+            Ok(texture)
+        } else {
+            Ok(Arc::new(SolidColor::from(attrs.color("color")?)))
+        }
+    }
+
+    /// Reads how the index of refraction of a dielectric varies with the
+    /// wavelength, if it does.
+    fn dispersion(attrs: &mut Attributes) -> Result<Option<Dispersion>> {
+        if let Some(preset) = attrs.get("preset") {
+            return match preset {
+                "bk7" => Ok(Some(Dispersion::BK7)),
+                "diamond" => Ok(Some(Dispersion::DIAMOND)),
+                _ => Err(attrs.error(format!("unknown dielectric `{}`", preset))),
+            };
+        }
+        if let Some(value) = attrs.get("cauchy") {
+            return match attrs.floats("cauchy", value)?[..] {
+                [a, b] => Ok(Some(Dispersion::Cauchy { a, b })),
+                _ => Err(attrs.error("`cauchy` expects two numbers `a,b`")),
+            };
+        }
+        if let Some(value) = attrs.get("sellmeier") {
+            return match attrs.floats("sellmeier", value)?[..] {
+                [b1, b2, b3, c1, c2, c3] => Ok(Some(Dispersion::Sellmeier {
+                    b: [b1, b2, b3],
+                    c: [c1, c2, c3],
+                })),
+                _ => Err(attrs.error("`sellmeier` expects six numbers `b1,b2,b3,c1,c2,c3`")),
+            };
+        }
+        Ok(None)
+    }
+
+    /// Reads the interior of a dielectric or glass, if it has one: the absorption,
+    /// given per unit of length by `absorption=` or as the `tint=` color
+    /// reached after `tint_distance=` (default 1), and the `priority=`.
+    fn interior(attrs: &mut Attributes) -> Result<Option<(Color, u32)>> {
+        let absorption = match attrs.get("tint") {
+            Some(_) => Some(Interior::absorption_from_tint(
+                attrs.color("tint")?,
+                attrs.float_or("tint_distance", 1.0)?,
+            )),
+            None if attrs.get("absorption").is_some() => Some(attrs.color("absorption")?),
+            None => None,
+        };
+        if absorption.is_none() && attrs.get("priority").is_none() {
+            return Ok(None);
+        }
+        Ok(Some((
+            absorption.unwrap_or_default(),
+            attrs.uint_or("priority", 0)?,
+        )))
+    }
+
+    fn texture(&mut self, kind: &str, attrs: &mut Attributes) -> Result<Arc<dyn Texture>> {
+        Ok(match kind {
+            "solid" => Arc::new(SolidColor::from(attrs.color("color")?)),
+            "checker" => {
+                let even = self.texture_ref(attrs, "even")?;
+                let odd = self.texture_ref(attrs, "odd")?;
+                match (even, odd) {
+                    (Some(even), Some(odd)) => Arc::new(CheckerTexture::new(even, odd)),
+                    _ => return Err(attrs.error("checker needs `even` and `odd`")),
+                }
+            }
+            "noise" => Arc::new(NoiseTexture::new(attrs.float_or("scale", 1.0)?)),
+            "image" => {
+                let path = self.base_dir.join(attrs.required("file")?);
+                let texture = ImageTexture::open(&path)
+                    .map_err(|e| attrs.error(format!("cannot load {}: {}", path.display(), e)))?;
+                Arc::new(texture)
+            }
+            _ => return Err(attrs.error(format!("unknown texture kind `{}`", kind))),
+        })
+    }
+
+    fn material(&mut self, kind: &str, attrs: &mut Attributes) -> Result<Arc<dyn Material>> {
+        Ok(match kind {
+            "lambertian" => Arc::new(Lambertian::new_with_shared_texture(self.albedo(attrs)?)),
+            "metal" => Arc::new(Metal::new(
+                attrs.color("color")?,
+                attrs.float_or("fuzz", 0.0)?,
+            )),
+            "dielectric" => {
+                let mut material = match Self::dispersion(attrs)? {
+                    Some(dispersion) => Dielectric::new_dispersive(dispersion),
+                    None => Dielectric::new(attrs.float_or("ir", 1.5)?),
+                };
+                if let Some((absorption, priority)) = Self::interior(attrs)? {
+                    material = material.with_absorption(absorption).with_priority(priority);
+                }
+                Arc::new(material)
+            }
+            "conductor" => {
+                let roughness = attrs.float_or("roughness", 0.0)?;
+                match attrs.get("preset") {
+                    Some("gold") => Arc::new(Conductor::gold(roughness)),
+                    Some("copper") => Arc::new(Conductor::copper(roughness)),
+                    Some("aluminium") => Arc::new(Conductor::aluminium(roughness)),
+                    Some(preset) => {
+                        return Err(attrs.error(format!("unknown conductor `{}`", preset)))
+                    }
+                    None => Arc::new(Conductor::new(
+                        attrs.color("eta")?,
+                        attrs.color("k")?,
+                        roughness,
+                    )),
+                }
+            }
+            "rough_dielectric" => {
+                let mut material = RoughDielectric::new(
+                    attrs.float_or("ir", 1.5)?,
+                    attrs.float_or("roughness", 0.0)?,
+                );
+                if let Some((absorption, priority)) = Self::interior(attrs)? {
+                    material = material.with_absorption(absorption).with_priority(priority);
+                }
+                Arc::new(material)
+            }
+            "principled" => {
+                let mut material = Principled::default();
+                for (key, parameter) in [
+                    ("base_color", &mut material.base_color),
+                    ("metallic", &mut material.metallic),
+                    ("roughness", &mut material.roughness),
+                    ("specular", &mut material.specular),
+                    ("specular_tint", &mut material.specular_tint),
+                    ("sheen", &mut material.sheen),
+                    ("clearcoat", &mut material.clearcoat),
+                    ("transmission", &mut material.transmission),
+                ] {
+                    if let Some(texture) = self.texture_ref(attrs, key)? {
+                        *parameter = texture;
+                    }
+                }
+                material.emission = self.texture_ref(attrs, "emission")?;
+                material.ior = attrs.float_or("ior", material.ior)?;
+                if let Some((absorption, priority)) = Self::interior(attrs)? {
+                    material = material.with_absorption(absorption).with_priority(priority);
+                }
+                Arc::new(material)
+            }
+            "mix" => Arc::new(MixMaterial::new(
+                self.material_ref(attrs, "first")?,
+                self.material_ref(attrs, "second")?,
+                self.texture_ref(attrs, "weight")?
+                    .ok_or_else(|| attrs.error("missing attribute `weight`"))?,
+            )),
+            "coated" => Arc::new(CoatedMaterial::new(
+                self.material_ref(attrs, "base")?,
+                attrs.float_or("ir", 1.5)?,
+                attrs.float_or("roughness", 0.0)?,
+            )),
+            "isotropic" => Arc::new(Isotropic::new_with_texture(self.albedo(attrs)?)),
+            _ => return Err(attrs.error(format!("unknown material kind `{}`", kind))),
+        })
+    }
+
+    /// Reads a `diffuse_light` apart from its area: with `power=`, the
+    /// power is its texture and the area is up to the caller.
+    fn diffuse_light(&mut self, attrs: &mut Attributes) -> Result<DiffuseLight> {
+        let mut light = match attrs.get("power") {
+            Some(power) => DiffuseLight::new_with_color(attrs.color_value("power", power)?),
+            None => DiffuseLight::new_with_texture(self.albedo(attrs)?),
+        };
+        if attrs.flag("two_sided") {
+            light = light.two_sided();
+        }
+        if let Some(value) = attrs.get("spot") {
+            match attrs.floats("spot", value)?[..] {
+                [inner, outer] if 0.0 <= inner && inner <= outer && outer <= 90.0 => {
+                    light = light.with_falloff(Falloff::Spot { inner, outer })
+                }
+                [_, _] => {
+                    return Err(attrs.error("`spot` angles must satisfy 0 <= inner <= outer <= 90"))
+                }
+                _ => return Err(attrs.error("`spot` expects two angles `inner,outer`")),
+            }
+        } else if let Some(value) = attrs.get("profile") {
+            let profile = attrs.floats("profile", value)?;
+            if profile.iter().any(|&v| v < 0.0) {
+                return Err(attrs.error("`profile` values must not be negative"));
+            }
+            light = light.with_falloff(Falloff::Profile(profile));
+        }
+        Ok(light)
+    }
+
+    /// The surface area of a shape of `kind`, after a uniform `scale=`.
+    fn shape_area(kind: &str, attrs: &mut Attributes) -> Result<Float> {
+        let extent = |(a, b): (Float, Float)| (b - a).abs();
+        let area = match kind {
+            "sphere" | "moving_sphere" => 4.0 * PI * attrs.float("radius")?.powi(2),
+            "xy_rect" => extent(attrs.range("x")?) * extent(attrs.range("y")?),
+            "xz_rect" => extent(attrs.range("x")?) * extent(attrs.range("z")?),
+            "yz_rect" => extent(attrs.range("y")?) * extent(attrs.range("z")?),
+            "box" => {
+                let size = attrs.vec3("max")? - attrs.vec3("min")?;
+                let (x, y, z) = (size.x().abs(), size.y().abs(), size.z().abs());
+                2.0 * (x * y + y * z + z * x)
+            }
+            "triangle" => {
+                let p0 = attrs.vec3("p0")?;
+                0.5 * (attrs.vec3("p1")? - p0)
+                    .cross(&(attrs.vec3("p2")? - p0))
+                    .length()
+            }
+            _ => return Err(attrs.error(format!("a light in watts on a `{}` needs `area=`", kind))),
+        };
+        let scale = match attrs.get("scale") {
+            Some(value) => attrs.color_value("scale", value)?,
+            None => Color::new(1.0, 1.0, 1.0),
+        };
+        if scale.x() != scale.y() || scale.y() != scale.z() {
+            return Err(attrs.error("a light in watts on an unevenly scaled shape needs `area=`"));
+        }
+        Ok(area * scale.x() * scale.x())
+    }
+
+    fn shape(&mut self, kind: &str, attrs: &mut Attributes) -> Result<Arc<dyn Hittable>> {
+        let material = match attrs.get("material") {
+            Some(name) => Some(match self.power_lights.get(name) {
+                Some(light) => {
+                    if !self.groups.is_empty() {
+                        return Err(attrs.error("a light in watts inside a group needs `area=`"));
+                    }
+                    let area = Self::shape_area(kind, attrs)?;
+                    Arc::new(light.clone().with_power(area)) as _
+                }
+                None => self
+                    .materials
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| attrs.error(format!("unknown material `{}`", name)))?,
+            }),
+            None if attrs.get("density").is_some() || attrs.get("noise").is_some() => {
+                Some(Arc::new(EmptyMaterial) as _)
+            }
+            None => None,
+        };
+
+        if kind == "mesh" {
+            let path = self.base_dir.join(attrs.required("file")?);
+            let default_material = material.unwrap_or_else(|| {
+                Arc::new(Lambertian::new_with_color(Color::new(0.73, 0.73, 0.73)))
+            });
+            let mesh = load_obj(&path, default_material)
+                .map_err(|e| attrs.error(format!("cannot load {}: {}", path.display(), e)))?;
+            return Ok(Arc::new(TriangleMesh::new(mesh)));
+        }
+
+        if kind == "volume" {
+            let path = self.base_dir.join(attrs.required("file")?);
+            let grid = match attrs.get("dims") {
+                Some(value) => {
+                    let dimensions = match attrs.floats("dims", value)?[..] {
+                        [x, y, z] if [x, y, z].iter().all(|&n| n >= 1.0 && n.fract() == 0.0) => {
+                            [x as usize, y as usize, z as usize]
+                        }
+                        _ => return Err(attrs.error("`dims` expects three sizes `x,y,z`")),
+                    };
+                    let [x, y, z] = dimensions.map(|n| n as Float);
+                    let bbox = AABB::new(
+                        attrs.vec3_or("min", Vec3::default())?,
+                        attrs.vec3_or("max", Vec3::new(x, y, z))?,
+                    );
+                    read_raw_grid(&path, dimensions, bbox)
+                }
+                None => load_voxel_grid(&path),
+            }
+            .map_err(|e| attrs.error(format!("cannot load {}: {}", path.display(), e)))?;
+            return Ok(Arc::new(GridMedium::new(
+                grid,
+                attrs.color_or("sigma_a", Color::default())?,
+                attrs.color_or("sigma_s", Color::new(1.0, 1.0, 1.0))?,
+                attrs.float_or("anisotropy", 0.0)?,
+                Matrix4::IDENTITY,
+            )));
+        }
+
+        let material = match material {
+            Some(material) => material,
+            None if SHAPES.contains(&kind) => {
+                return Err(attrs.error("missing attribute `material`"))
+            }
+            None => return Err(attrs.error(format!("unknown statement `{}`", kind))),
+        };
+
+        Ok(match kind {
+            "sphere" => Arc::new(Sphere::new(
+                attrs.vec3("center")?,
+                attrs.float("radius")?,
+                material,
+            )),
+            "moving_sphere" => {
+                let (time0, time1) = attrs.range("time")?;
+                Arc::new(MovingSphere::new(
+                    attrs.vec3("center0")?,
+                    attrs.vec3("center1")?,
+                    time0,
+                    time1,
+                    attrs.float("radius")?,
+                    material,
+                ))
+            }
+            "xy_rect" => {
+                let (x0, x1) = attrs.range("x")?;
+                let (y0, y1) = attrs.range("y")?;
+                Arc::new(XyRect::new(x0, x1, y0, y1, attrs.float("k")?, material))
+            }
+            "xz_rect" => {
+                let (x0, x1) = attrs.range("x")?;
+                let (z0, z1) = attrs.range("z")?;
+                Arc::new(XzRect::new(x0, x1, z0, z1, attrs.float("k")?, material))
+            }
+            "yz_rect" => {
+                let (y0, y1) = attrs.range("y")?;
+                let (z0, z1) = attrs.range("z")?;
+                Arc::new(YzRect::new(y0, y1, z0, z1, attrs.float("k")?, material))
+            }
+            "box" => Arc::new(BlockBox::new(
+                attrs.vec3("min")?,
+                attrs.vec3("max")?,
+                material,
+            )),
+            "triangle" => Arc::new(Triangle::new(
+                attrs.vec3("p0")?,
+                attrs.vec3("p1")?,
+                attrs.vec3("p2")?,
+                material,
+            )),
+            _ => return Err(attrs.error(format!("unknown statement `{}`", kind))),
+        })
+    }
+
+    /// Applies transforms, face flipping and media to a shape, then adds it
+    /// to the innermost open group or to the scene.
+    fn object(&mut self, shape: Arc<dyn Hittable>, mut attrs: Attributes) -> Result<()> {
+        let mut object = shape;
+        let mut matrix = None;
+        for index in 0..attrs.pairs.len() {
+            let (key, value) = attrs.pairs[index];
+            let step = match key {
+                "rotate_x" => {
+                    Matrix4::rotation(Vec3::new(1.0, 0.0, 0.0), attrs.parse_value(key, value)?)
+                }
+                "rotate_y" => {
+                    Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), attrs.parse_value(key, value)?)
+                }
+                "rotate_z" => {
+                    Matrix4::rotation(Vec3::new(0.0, 0.0, 1.0), attrs.parse_value(key, value)?)
+                }
+                "rotate" => match attrs.floats(key, value)?[..] {
+                    [x, y, z, angle] if x != 0.0 || y != 0.0 || z != 0.0 => {
+                        Matrix4::rotation(Vec3::new(x, y, z), angle)
+                    }
+                    _ => {
+                        return Err(
+                            attrs.error("`rotate` expects an axis and an angle `x,y,z,degrees`")
+                        )
+                    }
+                },
+                "scale" => {
+                    let factors = attrs.color_value(key, value)?;
+                    if factors.x() * factors.y() * factors.z() == 0.0 {
+                        return Err(attrs.error("`scale` factors must not be zero"));
+                    }
+                    Matrix4::scaling(factors)
+                }
+                "translate" => Matrix4::translation(attrs.vec3_value(key, value)?),
+                _ => continue,
+            };
+            matrix = Some(step * matrix.unwrap_or(Matrix4::IDENTITY));
+            attrs.used[index] = true;
+        }
+        if let Some(matrix) = matrix {
+            object = Arc::new(Transform::new(object, matrix));
+        }
+
+        if attrs.flag("flip") {
+            object = Arc::new(FlipFace::new(object));
+        }
+
+        if attrs.get("density").is_some() {
+            let density = attrs.float("density")?;
+            let phase = self
+                .texture_ref(&mut attrs, "phase")?
+                .unwrap_or_else(|| Arc::new(SolidColor::new(1.0, 1.0, 1.0)));
+            let phase: Arc<dyn Material> = match attrs.get("anisotropy") {
+                Some(_) => Arc::new(HenyeyGreenstein::new_with_texture(
+                    phase,
+                    attrs.float("anisotropy")?,
+                )),
+                None => Arc::new(Isotropic::new_with_texture(phase)),
+            };
+            object = Arc::new(ConstantMedium::new_with_phase(object, density, phase));
+        }
+
+        if attrs.get("noise").is_some() {
+            let scale = attrs.float("noise")?;
+            let depth = attrs.uint_or("noise_depth", 7)?;
+            let sigma_a = attrs.color_or("sigma_a", Color::default())?;
+            let sigma_s = attrs.color_or("sigma_s", Color::new(1.0, 1.0, 1.0))?;
+            let medium = HeterogeneousMedium::new(
+                object,
+                Arc::new(NoiseDensity::new(scale, depth)),
+                sigma_a,
+                sigma_s,
+            );
+            object = Arc::new(medium.with_anisotropy(attrs.float_or("anisotropy", 0.0)?));
+        }
+
+        let light = attrs.flag("light");
+        if light && !self.groups.is_empty() {
+            return Err(attrs.error("lights must be top level objects"));
+        }
+        attrs.finish()?;
+
+        if let Some(group) = self.groups.last_mut() {
+            group.add_shared(object);
+        } else {
+            if light {
+                self.scene.lights.add_shared(object.clone());
+            }
+            self.scene.world.add_shared(object);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HitRecord, Point3, Ray};
+
+    fn parse(source: &str) -> Result<Scene> {
+        Scene::parse(source, Path::new("."))
+    }
+
+    /// The line and message of the error `source` fails with.
+    fn error(source: &str) -> (usize, String) {
+        match parse(source) {
+            Ok(_) => panic!("{source:?} parsed"),
+            Err(error) => (error.line(), error.message),
+        }
+    }
+
+    #[test]
+    fn parses_statements() {
+        let scene = parse(
+            "image width=40 aspect=2 spp=3 depth=4
+camera lookfrom=0,1,2 lookat=0,0,0 vfov=30
+material white lambertian color=0.73
+material light diffuse_light color=15
+
+xz_rect x=-1,1 z=-1,1 k=2 material=light flip
+box min=0,0,0 max=1,1,1 material=white rotate_y=15 translate=2,0,0
+group
+sphere center=0,0,0 radius=1 material=white
+sphere center=2,0,0 radius=1 material=white
+end bvh translate=0,3,0
+",
+        )
+        .unwrap();
+        assert_eq!(scene.image_width, 40);
+        assert_eq!(scene.image_height(), 20);
+        assert_eq!(scene.samples_per_pixel, 3);
+        assert_eq!(scene.max_depth, 4);
+        assert_eq!(scene.camera.lookfrom, Point3::new(0.0, 1.0, 2.0));
+        assert_eq!(scene.camera.vfov, 30.0);
+        assert_eq!(scene.world.len(), 3);
+        assert_eq!(scene.light_list().len(), 1);
+    }
+
+    #[test]
+    fn strips_comments() {
+        assert_eq!(strip_comment("# a comment"), "");
+        assert_eq!(
+            strip_comment("sphere radius=1 # a comment"),
+            "sphere radius=1 "
+        );
+        assert_eq!(
+            strip_comment("sphere radius=1\t#comment"),
+            "sphere radius=1\t"
+        );
+        assert_eq!(strip_comment("mesh file=a#b.obj"), "mesh file=a#b.obj");
+        assert_eq!(strip_comment("mesh file=a#b.obj #c"), "mesh file=a#b.obj ");
+
+        let scene = parse("# header\nimage width=10 # trailing\n  # indented\n").unwrap();
+        assert_eq!(scene.image_width, 10);
+        // the `#` inside the name is part of it
+        let (line, message) = error("mesh file=no#such.obj");
+        assert_eq!(line, 1);
+        assert!(message.contains("no#such.obj"), "{message}");
+    }
+
+    #[test]
+    fn rejects_invalid_depth() {
+        assert_eq!(error("image depth=0").0, 1);
+        assert_eq!(error("\nimage depth=-3").0, 2);
+        assert_eq!(parse("image depth=1").unwrap().max_depth, 1);
+    }
+
+    #[test]
+    fn reports_errors_with_lines() {
+        let (line, message) = error(
+            "material m lambertian color=0.5\nsphere center=0,0,0 radius=1 material=m colour=red",
+        );
+        assert_eq!(line, 2);
+        assert!(message.contains("colour"), "{message}");
+        assert!(error("sphere center=0,0,0 radius=1 material=nope")
+            .1
+            .contains("nope"));
+        assert!(error("image width=ten").1.contains("width"));
+        assert!(error("unknown_statement").1.contains("unknown_statement"));
+        assert!(error("sphere center=0,0,0 radius=1").1.contains("material"));
+        let (line, message) = error(
+            "material m lambertian color=0.5\ngroup\nsphere center=0,0,0 radius=1 material=m",
+        );
+        assert_eq!(line, 3);
+        assert!(message.contains("end"), "{message}");
+        assert_eq!(error("end").0, 1);
+    }
+
+    #[test]
+    fn reads_interiors() {
+        let scene = parse(
+            "material water dielectric ir=1.33 priority=1
+material glass principled transmission=1 ior=1.5 tint=0.5,0.9,0.9 priority=2
+material m coated base=water
+sphere center=0,0,0 radius=1 material=m
+sphere center=3,0,0 radius=1 material=glass
+",
+        )
+        .unwrap();
+        let priority = |x: Float| {
+            let ray = Ray::new(Point3::new(x, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+            let mut rec = HitRecord::default();
+            assert!(scene.world.hit(&ray, 0.001, Float::INFINITY, &mut rec));
+            rec.material
+                .unwrap()
+                .interior()
+                .map(|interior| interior.priority)
+        };
+        // the coat forwards the interior of its base
+        assert_eq!(priority(0.0), Some(1));
+        assert_eq!(priority(3.0), Some(2));
+        assert!(error("material g principled priority=x")
+            .1
+            .contains("priority"));
+    }
+
+    #[test]
+    fn validates_light_falloffs() {
+        let light = |falloff: &str| {
+            parse(&format!(
+                "material l diffuse_light color=1 {falloff}\nsphere center=0,0,0 radius=1 material=l"
+            ))
+        };
+        assert!(light("spot=0,0").is_ok());
+        assert!(light("spot=10,45").is_ok());
+        assert!(light("spot=90,90").is_ok());
+        assert!(light("profile=1,0.5,0").is_ok());
+        for falloff in [
+            "spot=-5,30",
+            "spot=40,30",
+            "spot=10,95",
+            "spot=10",
+            "profile=1,-0.5",
+        ] {
+            let error = light(falloff).err().unwrap();
+            assert_eq!(error.line(), 1);
+            assert!(error
+                .message
+                .contains(&falloff[..falloff.find('=').unwrap()]));
+        }
+    }
+
+    #[test]
+    fn collects_light_targets() {
+        let scene = parse(
+            "material glass dielectric ir=1.5
+material light diffuse_light color=4
+sphere center=0,0,0 radius=1 material=glass light
+sphere center=3,0,0 radius=1 material=glass
+xz_rect x=-1,1 z=-1,1 k=5 material=light
+",
+        )
+        .unwrap();
+        assert_eq!(scene.world.len(), 3);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(!scene.lights[0].is_emissive());
+        assert_eq!(scene.light_list().len(), 1);
+        let (line, message) = error(
+            "material m lambertian color=0.5\ngroup\nsphere center=0,0,0 radius=1 material=m light\nend",
+        );
+        assert_eq!(line, 3);
+        assert!(message.contains("top level"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::{tone_map::xyz_to_rgb, Color, Float, Vec3};
+
+/// Shortest and longest wavelength, in nanometers, that paths carry.
+const LAMBDA_MIN: Float = 360.0;
+const LAMBDA_MAX: Float = 830.0;
+
+/// Integral of the color matching function `y` over the sampled range,
+/// which makes a spectrum of constant value 1 have a luminance of 1.
+const CIE_Y_INTEGRAL: Float = 106.922;
+
+/// XYZ of the spectrum of constant value 1, the equal-energy white, which
+/// the film maps to RGB white.
+const EQUAL_ENERGY_WHITE: Vec3 = Vec3::new(0.998539, 1.0, 0.999560);
+
+/// Start of the first of the bins of `SMITS_SPECTRA` and the width of each.
+const SMITS_START: Float = 380.0;
+const SMITS_BIN_WIDTH: Float = 34.0;
+
+/// Reflectance spectra of white, cyan, magenta, yellow, red, green and
+/// blue from Smits, "An RGB-to-Spectrum Conversion for Reflectances"
+/// (1999), in ten bins from 380 to 720 nm.
+const SMITS_SPECTRA: [[Float; 10]; 7] = [
+    [
+        1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+    ],
+    [
+        0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+    ],
+    [
+        1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+    ],
+    [
+        0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+    ],
+    [
+        0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+    ],
+    [
+        0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+    ],
+    [
+        1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+    ],
+];
+const WHITE: usize = 0;
+const CYAN: usize = 1;
+const MAGENTA: usize = 2;
+const YELLOW: usize = 3;
+const RED: usize = 4;
+const GREEN: usize = 5;
+const BLUE: usize = 6;
+
+/// How `render` represents light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Three color channels, those of the scene's colors.
+    Rgb,
+    /// A few wavelengths per path, at which the scene's colors are
+    /// upsampled to spectra, converted to RGB at the film. Slower to
+    /// converge, but shows dispersion.
+    Spectral,
+}
+
+impl RenderMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rgb" => Some(Self::Rgb),
+            "spectral" => Some(Self::Spectral),
+            _ => None,
+        }
+    }
+}
+
+/// The wavelengths, in nanometers, at which a path carries light in
+/// spectral mode, one per component of its colors: a hero wavelength and
+/// two more at even offsets from it in the sampling distribution
+/// (hero wavelength sampling).
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SampledWavelengths {
+    lambda: [Float; 3],
+    pdf: [Float; 3],
+}
+
+impl SampledWavelengths {
+    /// Samples wavelengths from a distribution that follows the sensitivity
+    /// of the eye, given one sample value.
+    pub fn sample(u: Float) -> Self {
+        let mut lambda = [0.0; 3];
+        let mut pdf = [0.0; 3];
+        for i in 0..3 {
+            // the distribution of visible wavelengths of pbrt, 4th edition
+            let u = (u + i as Float / 3.0).fract();
+            lambda[i] = 538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh();
+            lambda[i] = lambda[i].clamp(LAMBDA_MIN, LAMBDA_MAX);
+            pdf[i] = 0.0039398042 / (0.0072 * (lambda[i] - 538.0)).cosh().powi(2);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> Float {
+        self.lambda[0]
+    }
+
+    /// Keeps only the hero wavelength, for a path that took a direction
+    /// that only light of that wavelength takes. Light of the others
+    /// counts as zero from then on.
+    pub fn terminate_secondary(&mut self) {
+        if self.pdf[1] > 0.0 {
+            self.pdf = [self.pdf[0] / 3.0, 0.0, 0.0];
+        }
+    }
+
+    /// A spectrum of `color`, taken as linear sRGB, at the wavelengths.
+    pub fn upsample(&self, color: Color) -> Color {
+        let value = |i: usize| {
+            if self.pdf[i] > 0.0 {
+                rgb_to_spectrum(color, self.lambda[i])
+            } else {
+                0.0
+            }
+        };
+        Color::new(value(0), value(1), value(2))
+    }
+
+    /// The linear sRGB color of the light `radiance` carries at the
+    /// wavelengths.
+    pub fn to_rgb(self, radiance: Color) -> Color {
+        let radiance: [Float; 3] = radiance.into();
+        let mut xyz = Vec3::default();
+        for ((lambda, pdf), radiance) in self.lambda.iter().zip(self.pdf).zip(radiance) {
+            if pdf > 0.0 {
+                xyz += cie_xyz(*lambda) * (radiance / pdf);
+            }
+        }
+        let rgb = xyz_to_rgb(&(xyz / (3.0 * CIE_Y_INTEGRAL)));
+        let white = xyz_to_rgb(&EQUAL_ENERGY_WHITE);
+        Color::new(
+            rgb.x() / white.x(),
+            rgb.y() / white.y(),
+            rgb.z() / white.z(),
+        )
+    }
+}
+
+/// The CIE 1931 color matching functions at `lambda`, in the piecewise
+/// Gaussian fit of Wyman et al., "Simple Analytic Approximations to the
+/// CIE XYZ Color Matching Functions" (2013).
+fn cie_xyz(lambda: Float) -> Vec3 {
+    let g = |mu: Float, sigma_below: Float, sigma_above: Float| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Smits' upsampling of `color` at `lambda`: the smallest component as
+/// white, then the next one as the secondary color it shares with the
+/// largest, then the rest of the largest as a primary. Stays linear in the
+/// color, so it also suits emission above 1.
+fn rgb_to_spectrum(color: Color, lambda: Float) -> Float {
+    let bin = ((lambda - SMITS_START) / SMITS_BIN_WIDTH).clamp(0.0, 9.0) as usize;
+    let s = |spectrum: usize| SMITS_SPECTRA[spectrum][bin];
+    let (r, g, b) = (color.x().max(0.0), color.y().max(0.0), color.z().max(0.0));
+
+    if r <= g && r <= b {
+        r * s(WHITE)
+            + if g <= b {
+                (g - r) * s(CYAN) + (b - g) * s(BLUE)
+            } else {
+                (b - r) * s(CYAN) + (g - b) * s(GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * s(WHITE)
+            + if r <= b {
+                (r - g) * s(MAGENTA) + (b - r) * s(BLUE)
+            } else {
+                (b - g) * s(MAGENTA) + (r - b) * s(RED)
+            }
+    } else {
+        b * s(WHITE)
+            + if r <= g {
+                (r - b) * s(YELLOW) + (g - r) * s(GREEN)
+            } else {
+                (g - b) * s(YELLOW) + (r - g) * s(RED)
+            }
+    }
+}
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{Float, HitRecord, Hittable, Material, Onb, Point3, Ray, Vec3, AABB, PI};
+use crate::{
+    environment::uniform_sphere, Float, HitRecord, Hittable, Material, Onb, Point3, Ray, Sampler,
+    Vec3, AABB, PI,
+};
 
 pub struct Sphere {
     center: Point3,
@@ -73,16 +76,35 @@ impl Hittable for Sphere {
         if !self.hit(&Ray::new(*o, *v, 0.0), 0.001, Float::INFINITY, &mut rec) {
             return 0.0;
         }
-        let cos_theta_max =
-            (1.0 - self.radius * self.radius / (self.center - *o).length_squared()).sqrt();
+        let radius_squared = self.radius * self.radius;
+        let distance_squared = (self.center - *o).length_squared();
+        if distance_squared <= radius_squared {
+            // from inside, where only two-sided lights are seen, the whole
+            // surface is sampled by area
+            let cosine = (v.dot(&rec.normal) / v.length()).abs();
+            let area = 4.0 * PI * radius_squared;
+            return rec.t * rec.t * v.length_squared() / (cosine * area);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
         let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
-        return 1.0 / solid_angle;
+        1.0 / solid_angle
     }
 
-    fn random(&self, o: &Vec3) -> Vec3 {
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
         let direction = self.center - *o;
         let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return self.center + self.radius * uniform_sphere(sampler) - *o;
+        }
         let uvw = Onb::from(&direction);
-        uvw.local_v(&Vec3::random_to_sphere(self.radius, distance_squared))
+        uvw.local_v(&Vec3::random_to_sphere(
+            sampler,
+            self.radius,
+            distance_squared,
+        ))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
     }
 }
@@ -1,4 +1,10 @@
-use std::{mem, sync::Arc};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    mem,
+    path::Path,
+    sync::Arc,
+};
 
 use crate::{Color, Float, Perlin, Point3};
 
@@ -107,11 +113,44 @@ impl ImageTexture {
             height,
         }
     }
+
+    /// Loads a PNG image. 16-bit images are reduced to 8 bits, and gray
+    /// or transparent ones converted to RGB, dropping the alpha channel.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        buf.truncate(info.buffer_size());
+        let data = match info.color_type {
+            png::ColorType::Rgb => buf.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Rgba => buf.chunks_exact(4).map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Grayscale => buf.iter().map(|&g| [g; 3]).collect(),
+            png::ColorType::GrayscaleAlpha => buf.chunks_exact(2).map(|p| [p[0]; 3]).collect(),
+            png::ColorType::Indexed => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "indexed image was not expanded",
+                ))
+            }
+        };
+        Ok(Self {
+            data,
+            width: info.width as usize,
+            height: info.height as usize,
+        })
+    }
 }
 
 impl Texture for ImageTexture {
     fn value(&self, u: Float, v: Float, _p: &Point3) -> Color {
-        if self.data.len() == 0 {
+        if self.data.is_empty() {
             return Color::new(0.0, 1.0, 1.0);
         }
         let u = u.clamp(0.0, 1.0);
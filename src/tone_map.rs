@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::{Color, Float, Vec3};
+
+/// Maps linear scene radiance to display values, which are clipped to
+/// [0, 1] afterwards.
+pub trait ToneMap: Sync + Send {
+    fn map(&self, color: Color) -> Color;
+}
+
+pub(crate) fn luminance(color: &Color) -> Float {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+/// CIE XYZ to linear sRGB (Rec. 709 primaries, D65 white).
+pub(crate) fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    Color::new(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
+/// Leaves colors unchanged, so everything above 1 clips.
+pub struct Linear;
+
+impl ToneMap for Linear {
+    fn map(&self, color: Color) -> Color {
+        color
+    }
+}
+
+/// `L / (1 + L)` on luminance, which keeps the hue of bright colors.
+pub struct Reinhard;
+
+impl ToneMap for Reinhard {
+    fn map(&self, color: Color) -> Color {
+        let l = luminance(&color);
+        if l <= 0.0 {
+            return Color::default();
+        }
+        color * (1.0 / (1.0 + l))
+    }
+}
+
+/// Reinhard with a white point: luminance `white` and above maps to 1.
+pub struct ExtendedReinhard {
+    white: Float,
+}
+
+impl ExtendedReinhard {
+    pub fn new(white: Float) -> Self {
+        Self { white }
+    }
+}
+
+impl ToneMap for ExtendedReinhard {
+    fn map(&self, color: Color) -> Color {
+        let l = luminance(&color);
+        if l <= 0.0 {
+            return Color::default();
+        }
+        let mapped = l * (1.0 + l / (self.white * self.white)) / (1.0 + l);
+        color * (mapped / l)
+    }
+}
+
+/// Krzysztof Narkowicz's fit of the ACES filmic curve, applied per channel.
+pub struct AcesFilmic;
+
+impl ToneMap for AcesFilmic {
+    fn map(&self, color: Color) -> Color {
+        color.apply(|x| {
+            let x = x.max(0.0);
+            (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+        })
+    }
+}
+
+/// The sRGB opto-electronic transfer function, from linear [0, 1] to the
+/// encoded value.
+pub fn srgb_oetf(x: Float) -> Float {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Turns linear radiance into 8-bit sRGB: exposure, tone mapping, clipping
+/// and the sRGB transfer function.
+#[derive(Clone)]
+pub struct DisplayTransform {
+    /// Exposure adjustment in stops.
+    pub exposure: Float,
+    pub tone_map: Arc<dyn ToneMap>,
+}
+
+impl DisplayTransform {
+    pub fn new(exposure: Float, tone_map: Arc<dyn ToneMap>) -> Self {
+        Self { exposure, tone_map }
+    }
+
+    pub fn apply(&self, color: Color) -> [u8; 3] {
+        let color = color.apply(|x| if x.is_nan() { 0.0 } else { x });
+        let mapped = self.tone_map.map(color * self.exposure.exp2());
+        let encode = |x: Float| (srgb_oetf(x.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        [encode(mapped.x()), encode(mapped.y()), encode(mapped.z())]
+    }
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self::new(0.0, Arc::new(Linear))
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use crate::{
+    Color, Float, HitRecord, Hittable, HittableList, Matrix4, Point3, Ray, Sampler, Vec3, AABB,
+};
+
+/// An instance of a hittable placed by an affine transform. The inner object
+/// is shared, so the same geometry can be placed many times.
+pub struct Transform {
+    inner: Arc<dyn Hittable>,
+    matrix: Matrix4,
+    inverse: Matrix4,
+    /// Inverse transpose of `matrix`, which keeps normals perpendicular to
+    /// transformed surfaces.
+    normal_matrix: Matrix4,
+}
+
+impl Transform {
+    /// # Panics
+    ///
+    /// If `matrix` is not invertible.
+    pub fn new(p: Arc<dyn Hittable>, matrix: Matrix4) -> Self {
+        let inverse = matrix.inverse().expect("Transform matrix is singular");
+        Self {
+            inner: p,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        // the direction is not renormalized, so `t` is the same in both spaces
+        ray.transformed(
+            self.inverse.transform_point(&ray.origin()),
+            self.inverse.transform_vector(&ray.direction()),
+        )
+    }
+}
+
+impl Hittable for Transform {
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        let local_ray = self.local_ray(ray);
+        if !self.inner.hit(&local_ray, t_min, t_max, rec) {
+            return false;
+        }
+
+        // the normal already faces the ray, and the inverse transpose keeps
+        // its side, so `front_face` stays valid
+        rec.p = self.matrix.transform_point(&rec.p);
+        rec.normal = self
+            .normal_matrix
+            .transform_vector(&rec.normal)
+            .unit_vector();
+        true
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float, output_box: &mut AABB) -> bool {
+        let mut bbox = AABB::default();
+        if !self.inner.bounding_box(time0, time1, &mut bbox) {
+            return false;
+        }
+        let mut corners = Vec::with_capacity(8);
+        for x in [bbox.min().x(), bbox.max().x()] {
+            for y in [bbox.min().y(), bbox.max().y()] {
+                for z in [bbox.min().z(), bbox.max().z()] {
+                    corners.push(self.matrix.transform_point(&Point3::new(x, y, z)));
+                }
+            }
+        }
+        *output_box = corners
+            .into_iter()
+            .map(|v| AABB::new(v, v))
+            .reduce(AABB::surrounding_box)
+            .unwrap();
+        true
+    }
+
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+        let local_v = self.inverse.transform_vector(v);
+        let pdf = self
+            .inner
+            .pdf_value(&self.inverse.transform_point(o), &local_v);
+        // change of solid angle measure under the linear part of the matrix
+        let stretch = v.length() / local_v.length();
+        pdf * stretch.powi(3) / self.matrix.linear_determinant().abs()
+    }
+
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        let local = self.inner.random(&self.inverse.transform_point(o), sampler);
+        self.matrix.transform_vector(&local)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut inner_lights = HittableList::new();
+        self.inner.collect_lights(&mut inner_lights);
+        for light in inner_lights.iter() {
+            lights.add(Transform::new(light.clone(), self.matrix));
+        }
+    }
+
+    fn has_media(&self) -> bool {
+        self.inner.has_media()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        self.inner.transmittance(&self.local_ray(ray), t_min, t_max)
+    }
+}
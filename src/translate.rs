@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{Float, HitRecord, Hittable, Ray, Vec3, AABB};
+use crate::{Color, Float, HitRecord, Hittable, HittableList, Point3, Ray, Sampler, Vec3, AABB};
 
 pub struct Translate {
     inner: Arc<dyn Hittable>,
@@ -21,7 +21,7 @@ impl Hittable for Translate {
     where
         'a: 'b,
     {
-        let moved_ray = Ray::new(ray.origin() - self.offset, ray.direction(), ray.time());
+        let moved_ray = ray.transformed(ray.origin() - self.offset, ray.direction());
         if !self.inner.hit(&moved_ray, t_min, t_max, rec) {
             false
         } else {
@@ -42,4 +42,33 @@ impl Hittable for Translate {
             true
         }
     }
+
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+        self.inner.pdf_value(&(*o - self.offset), v)
+    }
+
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.inner.random(&(*o - self.offset), sampler)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.inner.is_emissive()
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        let mut inner_lights = HittableList::new();
+        self.inner.collect_lights(&mut inner_lights);
+        for light in inner_lights.iter() {
+            lights.add(Translate::new(light.clone(), self.offset));
+        }
+    }
+
+    fn has_media(&self) -> bool {
+        self.inner.has_media()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: Float, t_max: Float) -> Color {
+        let moved_ray = ray.transformed(ray.origin() - self.offset, ray.direction());
+        self.inner.transmittance(&moved_ray, t_min, t_max)
+    }
 }
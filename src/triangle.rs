@@ -0,0 +1,301 @@
+use std::sync::Arc;
+
+use crate::{
+    BvhNode, Float, HitRecord, Hittable, HittableList, Material, Point3, Ray, Sampler, Vec3, AABB,
+};
+
+/// Möller–Trumbore ray/triangle intersection. Returns `t` and the
+/// barycentric coordinates of `p[1]` and `p[2]`.
+fn intersect(
+    p: &[Point3; 3],
+    ray: &Ray,
+    t_min: Float,
+    t_max: Float,
+) -> Option<(Float, Float, Float)> {
+    let edge1 = p[1] - p[0];
+    let edge2 = p[2] - p[0];
+    let h = ray.direction().cross(&edge2);
+    let a = edge1.dot(&h);
+    if a.abs() < 1e-12 {
+        return None;
+    }
+    let f = 1.0 / a;
+    let s = ray.origin() - p[0];
+    let b1 = f * s.dot(&h);
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let b2 = f * ray.direction().dot(&q);
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = f * edge2.dot(&q);
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn interpolate<T>(values: &[T; 3], b1: Float, b2: Float, f: impl Fn(&T) -> Vec3) -> Vec3 {
+    (1.0 - b1 - b2) * f(&values[0]) + b1 * f(&values[1]) + b2 * f(&values[2])
+}
+
+fn bounding_box(p: &[Point3; 3]) -> AABB {
+    const PAD: Float = 0.0001;
+    let pad = Vec3::new(PAD, PAD, PAD);
+    let small = Point3::new(
+        p[0].x().min(p[1].x()).min(p[2].x()),
+        p[0].y().min(p[1].y()).min(p[2].y()),
+        p[0].z().min(p[1].z()).min(p[2].z()),
+    );
+    let big = Point3::new(
+        p[0].x().max(p[1].x()).max(p[2].x()),
+        p[0].y().max(p[1].y()).max(p[2].y()),
+        p[0].z().max(p[1].z()).max(p[2].z()),
+    );
+    AABB::new(small - pad, big + pad)
+}
+
+/// Fills `rec` for a hit at `t` with barycentric coordinates `b1`, `b2`.
+/// The face side comes from the geometric normal, the shading normal is
+/// interpolated from the vertex normals if there are any.
+#[allow(clippy::too_many_arguments)]
+fn fill_record<'a>(
+    p: &[Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(Float, Float); 3]>,
+    material: &'a dyn Material,
+    ray: &Ray,
+    (t, b1, b2): (Float, Float, Float),
+    rec: &mut HitRecord<'a>,
+) {
+    let geometric_normal = (p[1] - p[0]).cross(&(p[2] - p[0])).unit_vector();
+    rec.t = t;
+    rec.p = ray.at(t);
+    rec.set_face_normal(ray, geometric_normal);
+    if let Some(normals) = normals {
+        let shading_normal = interpolate(&normals, b1, b2, |n| *n).unit_vector();
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+    }
+    (rec.u, rec.v) = match uvs {
+        Some(uvs) => {
+            let uv = interpolate(&uvs, b1, b2, |(u, v)| Vec3::new(*u, *v, 0.0));
+            (uv.x(), uv.y())
+        }
+        None => (b1, b2),
+    };
+    rec.material = Some(material);
+}
+
+fn area(p: &[Point3; 3]) -> Float {
+    0.5 * (p[1] - p[0]).cross(&(p[2] - p[0])).length()
+}
+
+/// Solid angle density of sampling the triangle uniformly by area, as seen
+/// from `o` in direction `v`.
+fn pdf_value(p: &[Point3; 3], o: &Point3, v: &Vec3) -> Float {
+    let Some((t, _, _)) = intersect(p, &Ray::new(*o, *v, 0.0), 0.001, Float::INFINITY) else {
+        return 0.0;
+    };
+    let normal = (p[1] - p[0]).cross(&(p[2] - p[0])).unit_vector();
+    let distance_squared = t * t * v.length_squared();
+    let cosine = (v.dot(&normal) / v.length()).abs();
+    distance_squared / (cosine * area(p))
+}
+
+fn random_point(p: &[Point3; 3], sampler: &mut dyn Sampler) -> Point3 {
+    let (r1, r2) = sampler.get_2d();
+    let r1 = r1.sqrt();
+    (1.0 - r1) * p[0] + r1 * (1.0 - r2) * p[1] + r1 * r2 * p[2]
+}
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(Float, Float); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(p0: Point3, p1: Point3, p2: Point3, material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices: [p0, p1, p2],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(Float, Float); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        match intersect(&self.vertices, ray, t_min, t_max) {
+            Some(hit) => {
+                fill_record(
+                    &self.vertices,
+                    self.normals,
+                    self.uvs,
+                    &*self.material,
+                    ray,
+                    hit,
+                    rec,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: Float, _time1: Float, output_box: &mut AABB) -> bool {
+        *output_box = bounding_box(&self.vertices);
+        true
+    }
+
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+        pdf_value(&self.vertices, o, v)
+    }
+
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        random_point(&self.vertices, sampler) - *o
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+/// A triangle of a `Mesh`, as indices into its vertex buffers.
+#[derive(Debug, Clone, Copy)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+/// Indexed triangles sharing vertex buffers and a material table.
+#[derive(Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(Float, Float)>,
+    pub faces: Vec<MeshFace>,
+    pub materials: Vec<Arc<dyn Material>>,
+}
+
+impl Mesh {
+    fn vertices(&self, face: &MeshFace) -> [Point3; 3] {
+        face.vertices.map(|i| self.positions[i])
+    }
+}
+
+struct MeshTriangle {
+    mesh: Arc<Mesh>,
+    index: usize,
+}
+
+impl MeshTriangle {
+    fn face(&self) -> &MeshFace {
+        &self.mesh.faces[self.index]
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        let mesh = &*self.mesh;
+        let face = self.face();
+        let vertices = mesh.vertices(face);
+        match intersect(&vertices, ray, t_min, t_max) {
+            Some(hit) => {
+                fill_record(
+                    &vertices,
+                    face.normals.map(|n| n.map(|i| mesh.normals[i])),
+                    face.uvs.map(|uv| uv.map(|i| mesh.uvs[i])),
+                    &*mesh.materials[face.material],
+                    ray,
+                    hit,
+                    rec,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self, _time0: Float, _time1: Float, output_box: &mut AABB) -> bool {
+        *output_box = bounding_box(&self.mesh.vertices(self.face()));
+        true
+    }
+
+    fn pdf_value(&self, o: &Point3, v: &Vec3) -> Float {
+        pdf_value(&self.mesh.vertices(self.face()), o, v)
+    }
+
+    fn random(&self, o: &Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+        random_point(&self.mesh.vertices(self.face()), sampler) - *o
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.materials[self.face().material].is_emissive()
+    }
+}
+
+/// A `Mesh` as a hittable, with its own BVH over the triangles.
+pub struct TriangleMesh {
+    triangles: HittableList,
+    bvh: BvhNode,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: Mesh) -> Self {
+        assert!(!mesh.faces.is_empty(), "No faces to construct TriangleMesh");
+        let mesh = Arc::new(mesh);
+        let mut triangles = HittableList::new();
+        for index in 0..mesh.faces.len() {
+            triangles.add(MeshTriangle {
+                mesh: mesh.clone(),
+                index,
+            });
+        }
+        let bvh = BvhNode::new(&triangles, 0.0, 1.0);
+        Self { triangles, bvh }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit<'a, 'b>(&'a self, ray: &Ray, t_min: Float, t_max: Float, rec: &mut HitRecord<'b>) -> bool
+    where
+        'a: 'b,
+    {
+        self.bvh.hit(ray, t_min, t_max, rec)
+    }
+
+    fn bounding_box(&self, time0: Float, time1: Float, output_box: &mut AABB) -> bool {
+        self.bvh.bounding_box(time0, time1, output_box)
+    }
+
+    fn collect_lights(&self, lights: &mut HittableList) {
+        self.triangles.collect_lights(lights);
+    }
+}
@@ -1,10 +1,8 @@
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Range, Sub, SubAssign};
 
-use rand::random;
+use crate::{Float, Rng, Sampler, PI};
 
-use crate::{Float, PI};
-
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vec3(Float, Float, Float);
 
 impl Vec3 {
@@ -12,41 +10,41 @@ impl Vec3 {
         Self(a, b, c)
     }
 
-    pub fn random() -> Self {
-        Self(random(), random(), random())
+    pub fn random(rng: &mut Rng) -> Self {
+        Self(rng.float(), rng.float(), rng.float())
     }
 
-    pub fn random_range(range: Range<Float>) -> Self {
+    pub fn random_range(rng: &mut Rng, range: Range<Float>) -> Self {
         let start = range.start;
         let end = range.end;
-        Self::new(start, start, start) + Self::random() * (end - start)
+        Self::new(start, start, start) + Self::random(rng) * (end - start)
     }
 
-    pub fn random_in_unit_sphere() -> Self {
-        Self::random_unit_vector() * random::<Float>()
+    pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Self {
+        Self::random_unit_vector(sampler) * sampler.get_1d()
     }
 
-    pub fn random_unit_vector() -> Self {
-        let alpha = random::<Float>() * PI;
-        let beta = random::<Float>() * PI * 2.0;
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Self {
+        let (r1, r2) = sampler.get_2d();
+        let alpha = r1 * PI;
+        let beta = r2 * PI * 2.0;
         let (a, z) = alpha.sin_cos();
         let (y, x) = beta.sin_cos();
 
         Self(x * a, y * a, z)
     }
 
-    pub fn random_in_hemisphere(normal: &Self) -> Self {
-        let v = Self::random_in_unit_sphere();
-        if v.dot(&normal) > 0.0 {
+    pub fn random_in_hemisphere(sampler: &mut dyn Sampler, normal: &Self) -> Self {
+        let v = Self::random_in_unit_sphere(sampler);
+        if v.dot(normal) > 0.0 {
             v
         } else {
             -v
         }
     }
 
-    pub fn random_cosine_direction() -> Self {
-        let r1 = random::<Float>();
-        let r2 = random::<Float>();
+    pub fn random_cosine_direction(sampler: &mut dyn Sampler) -> Self {
+        let (r1, r2) = sampler.get_2d();
         let z = (1.0 - r2).sqrt();
         let phi = 2.0 * PI * r1;
         let r2_t = r2.sqrt();
@@ -56,9 +54,12 @@ impl Vec3 {
         Self(x, y, z)
     }
 
-    pub fn random_to_sphere(radius: Float, distance_squared: Float) -> Self {
-        let r1 = random::<Float>();
-        let r2 = random::<Float>();
+    pub fn random_to_sphere(
+        sampler: &mut dyn Sampler,
+        radius: Float,
+        distance_squared: Float,
+    ) -> Self {
+        let (r1, r2) = sampler.get_2d();
         let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
         let phi = 2.0 * PI * r1;
         let (sin, cos) = phi.sin_cos();
@@ -129,7 +130,7 @@ impl Vec3 {
         Self(
             self.0,
             cos * self.1 - sin * self.2,
-            sin * self.1 - cos * self.2,
+            sin * self.1 + cos * self.2,
         )
     }
 
@@ -0,0 +1,497 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{Float, Point3, Vec3, VoxelGrid, AABB};
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The number of voxels of a grid of `dimensions`, if it can be addressed.
+fn voxel_count(dimensions: [usize; 3]) -> io::Result<usize> {
+    dimensions
+        .iter()
+        .try_fold(1usize, |count, &n| count.checked_mul(n))
+        .ok_or_else(|| invalid_data("grid is too large"))
+}
+
+/// Splits `data` after the first empty line, returning the header text and
+/// the rest.
+fn split_header(data: &[u8]) -> io::Result<(&str, &[u8])> {
+    let mut start = 0;
+    let end = loop {
+        let Some(length) = data[start..].iter().position(|&b| b == b'\n') else {
+            // a header without data, e.g. a detached NRRD header
+            break data.len();
+        };
+        let line = &data[start..start + length];
+        start += length + 1;
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            break start;
+        }
+    };
+    let header =
+        std::str::from_utf8(&data[..end]).map_err(|_| invalid_data("header is not text"))?;
+    Ok((header, &data[end..]))
+}
+
+/// The sample types of voxel files.
+#[derive(Clone, Copy)]
+enum SampleType {
+    U8,
+    U16,
+    I16,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    const fn size(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 | Self::I16 => 2,
+            Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    fn decode(self, bytes: &[u8], big_endian: bool) -> Float {
+        macro_rules! decode {
+            ($t: ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                (if big_endian {
+                    <$t>::from_be_bytes(bytes)
+                } else {
+                    <$t>::from_le_bytes(bytes)
+                }) as Float
+            }};
+        }
+        match self {
+            Self::U8 => bytes[0] as Float,
+            Self::U16 => decode!(u16),
+            Self::I16 => decode!(i16),
+            Self::F32 => decode!(f32),
+            Self::F64 => decode!(f64),
+        }
+    }
+}
+
+fn decode_samples(
+    data: &[u8],
+    count: usize,
+    sample_type: SampleType,
+    big_endian: bool,
+) -> io::Result<Vec<Float>> {
+    let length = count
+        .checked_mul(sample_type.size())
+        .ok_or_else(|| invalid_data("grid is too large"))?;
+    if data.len() < length {
+        return Err(invalid_data("voxel data is truncated"));
+    }
+    Ok(data[..length]
+        .chunks_exact(sample_type.size())
+        .map(|bytes| sample_type.decode(bytes, big_endian))
+        .collect())
+}
+
+/// Reads a headerless file of little endian 32-bit floats holding a grid
+/// of `dimensions` samples, x varying fastest, that divides `bbox` into
+/// cells.
+pub fn read_raw_grid(
+    path: impl AsRef<Path>,
+    dimensions: [usize; 3],
+    bbox: AABB,
+) -> io::Result<VoxelGrid> {
+    let data = fs::read(path)?;
+    let count = voxel_count(dimensions)?;
+    let values = decode_samples(&data, count, SampleType::F32, false)?;
+    Ok(VoxelGrid::new(values, dimensions, bbox))
+}
+
+/// Parses the numbers of an NRRD vector such as `(1,0,0)`.
+fn nrrd_vector(text: &str) -> Option<[Float; 3]> {
+    let values: Vec<Float> = text
+        .trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+/// Reads a three dimensional scalar NRRD file (`.nrrd`, or a detached
+/// `.nhdr` header naming its `data file`) with raw encoding.
+///
+/// Samples keep their stored values. They sit at `space origin` plus
+/// multiples of the axis aligned `spacings` or `space directions`, by
+/// default at the integers from 0, and each fills the cell around it.
+pub fn read_nrrd(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    if !data.starts_with(b"NRRD000") {
+        return Err(invalid_data("not an NRRD file"));
+    }
+    let (header, attached) = split_header(&data)?;
+
+    let mut sample_type = None;
+    let mut dimensions = None;
+    let mut big_endian = false;
+    let mut origin = [0.0; 3];
+    let mut spacing = [1.0; 3];
+    let mut byte_skip = 0;
+    let mut data_file: Option<PathBuf> = None;
+    for line in header.lines().skip(1) {
+        // comments and key/value pairs carry nothing we need
+        if line.starts_with('#') || line.contains(":=") {
+            continue;
+        }
+        let Some((field, value)) = line.split_once(": ") else {
+            continue;
+        };
+        let value = value.trim();
+        match field.trim() {
+            "type" => {
+                sample_type = Some(match value {
+                    "uchar" | "unsigned char" | "uint8" | "uint8_t" => SampleType::U8,
+                    "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                        SampleType::U16
+                    }
+                    "short" | "short int" | "signed short" | "signed short int" | "int16"
+                    | "int16_t" => SampleType::I16,
+                    "float" => SampleType::F32,
+                    "double" => SampleType::F64,
+                    _ => return Err(invalid_data(format!("unsupported type `{}`", value))),
+                })
+            }
+            "dimension" if value != "3" => {
+                return Err(invalid_data("only three dimensional grids are supported"))
+            }
+            "sizes" => {
+                let sizes: Vec<usize> = value
+                    .split_whitespace()
+                    .map(|size| size.parse().ok().filter(|&size| size > 0))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid_data("bad sizes"))?;
+                dimensions =
+                    Some(<[usize; 3]>::try_from(sizes).map_err(|_| invalid_data("bad sizes"))?);
+            }
+            "encoding" if value != "raw" => {
+                return Err(invalid_data("only raw encoding is supported"))
+            }
+            "endian" => big_endian = value == "big",
+            "byte skip" => {
+                byte_skip = value
+                    .parse()
+                    .map_err(|_| invalid_data("unsupported byte skip"))?
+            }
+            "space origin" => {
+                origin = nrrd_vector(value).ok_or_else(|| invalid_data("bad space origin"))?
+            }
+            "spacings" => {
+                let values: Vec<Float> = value
+                    .split_whitespace()
+                    .map(|v| v.parse().ok())
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid_data("bad spacings"))?;
+                spacing = values
+                    .try_into()
+                    .map_err(|_| invalid_data("bad spacings"))?;
+            }
+            "space directions" => {
+                let directions: Vec<[Float; 3]> = value
+                    .split_inclusive(')')
+                    .map(str::trim)
+                    .filter(|direction| !direction.is_empty())
+                    .map(nrrd_vector)
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid_data("bad space directions"))?;
+                if directions.len() != 3 {
+                    return Err(invalid_data("bad space directions"));
+                }
+                for (axis, direction) in directions.iter().enumerate() {
+                    if (0..3).any(|i| i != axis && direction[i] != 0.0) {
+                        return Err(invalid_data("only axis aligned grids are supported"));
+                    }
+                    spacing[axis] = direction[axis];
+                }
+            }
+            "data file" | "datafile" => data_file = Some(path.with_file_name(value)),
+            _ => {}
+        }
+    }
+
+    let sample_type = sample_type.ok_or_else(|| invalid_data("missing type"))?;
+    let dimensions = dimensions.ok_or_else(|| invalid_data("missing sizes"))?;
+    if spacing.iter().any(|&s| s <= 0.0) {
+        return Err(invalid_data("spacings must be positive"));
+    }
+    let detached;
+    let raw = match data_file {
+        Some(data_file) => {
+            detached = fs::read(data_file)?;
+            &detached[..]
+        }
+        None => attached,
+    };
+    let raw = raw
+        .get(byte_skip..)
+        .ok_or_else(|| invalid_data("voxel data is truncated"))?;
+    let count = voxel_count(dimensions)?;
+    let values = decode_samples(raw, count, sample_type, big_endian)?;
+
+    let origin = Point3::new(origin[0], origin[1], origin[2]);
+    let spacing = Vec3::new(spacing[0], spacing[1], spacing[2]);
+    let size = Vec3::new(
+        dimensions[0] as Float,
+        dimensions[1] as Float,
+        dimensions[2] as Float,
+    );
+    let bbox = AABB::new(
+        origin - 0.5 * spacing,
+        origin + (size - Vec3::new(0.5, 0.5, 0.5)) * spacing,
+    );
+    Ok(VoxelGrid::new(values, dimensions, bbox))
+}
+
+/// Reads a sparse grid of blocks, which stores only the blocks of a grid
+/// that are not empty. The text header
+///
+/// ```text
+/// VXB1
+/// dimensions 256 128 256
+/// block 8
+/// bbox -1 0 -1 1 1 1
+/// ```
+///
+/// ends with an empty line and is followed by blocks to the end of the
+/// file, each the three little endian 32-bit block indices along x, y and
+/// z and then `block`³ little endian 32-bit floats, x varying fastest.
+/// Voxels outside the blocks are empty. `bbox` (min then max) defaults to
+/// one unit per voxel from the origin.
+pub fn read_block_grid(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+    let data = fs::read(path)?;
+    if !data.starts_with(b"VXB1") {
+        return Err(invalid_data("not a voxel block file"));
+    }
+    let (header, mut blocks) = split_header(&data)?;
+
+    let mut dimensions = None;
+    let mut block_size = None;
+    let mut bbox = None;
+    for line in header.lines().skip(1) {
+        let mut words = line.split_whitespace();
+        let Some(key) = words.next() else {
+            continue;
+        };
+        let numbers: Vec<Float> = words
+            .map(|word| word.parse().ok())
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid_data(format!("bad `{}`", key)))?;
+        let positive = |n: Float| (n >= 1.0 && n.fract() == 0.0).then_some(n as usize);
+        match (key, &numbers[..]) {
+            ("dimensions", &[x, y, z]) => {
+                dimensions = Some(
+                    [positive(x), positive(y), positive(z)]
+                        .into_iter()
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| invalid_data("bad dimensions"))?,
+                )
+            }
+            ("block", &[size]) => {
+                block_size = Some(positive(size).ok_or_else(|| invalid_data("bad block size"))?)
+            }
+            ("bbox", &[x0, y0, z0, x1, y1, z1]) => {
+                bbox = Some(AABB::new(Point3::new(x0, y0, z0), Point3::new(x1, y1, z1)))
+            }
+            _ => return Err(invalid_data(format!("bad header line `{}`", line))),
+        }
+    }
+
+    let dimensions: [usize; 3] = dimensions
+        .ok_or_else(|| invalid_data("missing dimensions"))?
+        .try_into()
+        .unwrap();
+    let block_size = block_size.ok_or_else(|| invalid_data("missing block size"))?;
+    let bbox = bbox.unwrap_or_else(|| {
+        let [x, y, z] = dimensions.map(|n| n as Float);
+        AABB::new(Point3::default(), Point3::new(x, y, z))
+    });
+
+    let [nx, ny, nz] = dimensions;
+    let mut values = vec![0.0; voxel_count(dimensions)?];
+    let block_samples = block_size
+        .checked_pow(3)
+        .filter(|&n| n <= (usize::MAX - 12) / 4)
+        .ok_or_else(|| invalid_data("bad block size"))?;
+    let block_bytes = 12 + 4 * block_samples;
+    while !blocks.is_empty() {
+        if blocks.len() < block_bytes {
+            return Err(invalid_data("voxel block is truncated"));
+        }
+        let (block, rest) = blocks.split_at(block_bytes);
+        blocks = rest;
+        let index = |i: usize| u32::from_le_bytes(block[4 * i..4 * i + 4].try_into().unwrap());
+        let origin = [index(0), index(1), index(2)].map(|i| i as usize * block_size);
+        let samples = decode_samples(&block[12..], block_samples, SampleType::F32, false)?;
+        for (i, value) in samples.into_iter().enumerate() {
+            let x = origin[0] + i % block_size;
+            let y = origin[1] + i / block_size % block_size;
+            let z = origin[2] + i / (block_size * block_size);
+            // blocks at the far edges may stick out of the grid
+            if x < nx && y < ny && z < nz {
+                values[(z * ny + y) * nx + x] = value;
+            }
+        }
+    }
+    Ok(VoxelGrid::new(values, dimensions, bbox))
+}
+
+/// Reads a voxel grid, choosing the reader by extension: NRRD for `.nrrd`
+/// and `.nhdr`, sparse blocks for `.vxb`.
+pub fn load_voxel_grid(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+    let path = path.as_ref();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("nrrd" | "nhdr") => read_nrrd(path),
+        Some("vxb") => read_block_grid(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "unknown voxel grid format, expected .nrrd, .nhdr or .vxb",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DensityField;
+
+    /// Writes `files` into a fresh directory named after `test` and
+    /// returns it.
+    fn write_files(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voxels-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn load_error(test: &str, name: &str, contents: &[u8]) -> String {
+        let dir = write_files(test, &[(name, contents)]);
+        match load_voxel_grid(dir.join(name)) {
+            Ok(_) => panic!("{name} loaded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_attached_nrrd() {
+        let mut file = b"NRRD0004
+# a comment
+type: unsigned short
+dimension: 3
+sizes: 2 1 1
+endian: big
+encoding: raw
+space origin: (1,2,3)
+spacings: 2 1 1
+key:=value
+
+"
+        .to_vec();
+        file.extend([0, 7, 1, 0]);
+        let dir = write_files("nrrd", &[("grid.nrrd", &file)]);
+        let grid = load_voxel_grid(dir.join("grid.nrrd")).unwrap();
+        assert_eq!(grid.dimensions(), [2, 1, 1]);
+        assert_eq!(grid.bbox().min(), Point3::new(0.0, 1.5, 2.5));
+        assert_eq!(grid.bbox().max(), Point3::new(4.0, 2.5, 3.5));
+        // samples sit at the origin plus multiples of the spacings
+        assert_eq!(grid.density(&Point3::new(1.0, 2.0, 3.0)), 7.0);
+        assert_eq!(grid.density(&Point3::new(3.0, 2.0, 3.0)), 256.0);
+        assert_eq!(grid.density(&Point3::new(2.0, 2.0, 3.0)), 131.5);
+        assert_eq!(grid.density(&Point3::new(5.0, 2.0, 3.0)), 0.0);
+    }
+
+    #[test]
+    fn reads_detached_nrrd() {
+        let header = b"NRRD0005
+type: float
+dimension: 3
+sizes: 1 1 2
+encoding: raw
+byte skip: 4
+space directions: (1,0,0) (0,1,0) (0,0,0.5)
+data file: grid.raw
+";
+        let data = f32_bytes(&[99.0, 0.25, -1.0]);
+        let dir = write_files("nhdr", &[("grid.nhdr", header), ("grid.raw", &data)]);
+        let grid = load_voxel_grid(dir.join("grid.nhdr")).unwrap();
+        assert_eq!(grid.dimensions(), [1, 1, 2]);
+        assert_eq!(grid.bbox().max(), Point3::new(0.5, 0.5, 0.75));
+        assert_eq!(grid.density(&Point3::new(0.0, 0.0, 0.0)), 0.25);
+        // negative samples are empty
+        assert_eq!(grid.density(&Point3::new(0.0, 0.0, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn reads_block_grids() {
+        let mut file = b"VXB1\ndimensions 3 2 2\nblock 2\n\n".to_vec();
+        // the block at x = 2 sticks out of the grid along x
+        file.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        file.extend(f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]));
+        let dir = write_files("vxb", &[("grid.vxb", &file)]);
+        let grid = load_voxel_grid(dir.join("grid.vxb")).unwrap();
+        assert_eq!(grid.dimensions(), [3, 2, 2]);
+        // one unit per voxel from the origin by default
+        assert_eq!(grid.bbox().max(), Point3::new(3.0, 2.0, 2.0));
+        assert_eq!(grid.density(&Point3::new(2.5, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.density(&Point3::new(2.5, 1.5, 1.5)), 7.0);
+        // voxels outside the blocks are empty
+        assert_eq!(grid.density(&Point3::new(0.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn reads_raw_grids() {
+        let dir = write_files("raw", &[("grid.raw", &f32_bytes(&[1.0, 2.0]))]);
+        let bbox = AABB::new(Point3::default(), Point3::new(2.0, 1.0, 1.0));
+        let grid = read_raw_grid(dir.join("grid.raw"), [2, 1, 1], bbox).unwrap();
+        assert_eq!(grid.density(&Point3::new(1.5, 0.5, 0.5)), 2.0);
+        assert!(read_raw_grid(dir.join("grid.raw"), [3, 1, 1], bbox).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let nrrd = |fields: &str| format!("NRRD0004\ntype: uchar\ndimension: 3\n{fields}\n\n");
+        let error = load_error("truncated", "a.nrrd", nrrd("sizes: 2 2 2").as_bytes());
+        assert_eq!(error, "voxel data is truncated");
+        let error = load_error("encoding", "b.nrrd", nrrd("encoding: gzip").as_bytes());
+        assert_eq!(error, "only raw encoding is supported");
+        let fields = "sizes: 1 1 1\nspace directions: (1,1,0) (0,1,0) (0,0,1)";
+        let error = load_error("directions", "c.nrrd", nrrd(fields).as_bytes());
+        assert_eq!(error, "only axis aligned grids are supported");
+        let error = load_error("sizes", "d.nrrd", nrrd("sizes: 1 0 1").as_bytes());
+        assert_eq!(error, "bad sizes");
+        assert_eq!(load_error("magic", "e.nrrd", b"P6\n"), "not an NRRD file");
+
+        let mut file = b"VXB1\ndimensions 2 2 2\nblock 2\n\n".to_vec();
+        file.extend([0; 12 + 4 * 7]);
+        assert_eq!(
+            load_error("block", "f.vxb", &file),
+            "voxel block is truncated"
+        );
+        let error = load_error(
+            "block_size",
+            "g.vxb",
+            b"VXB1\ndimensions 2 2 2\nblock 0\n\n",
+        );
+        assert_eq!(error, "bad block size");
+        assert!(load_error("extension", "h.vdb", b"").contains("unknown voxel grid format"));
+    }
+}